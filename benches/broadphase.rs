@@ -0,0 +1,84 @@
+//! Time each broadphase strategy on increasingly large fields of circles
+//!
+//! Run with `cargo bench`. The crate has no library target, so the physics module is compiled in from the source tree.
+
+#![allow(dead_code, unused_imports)]
+
+use std::time::{Duration, Instant};
+
+#[path = "../src"]
+mod src {
+    pub mod physics;
+}
+
+use src::physics::broadphase::{Broadphase, BruteForce, SpatialHashGrid, SweepAndPrune};
+use src::physics::random::Xorshift;
+use src::physics::Aabb;
+
+/// Randomly placed circles with unit diameter
+///
+/// The field grows with the circle count so the density, and so the number of overlaps per circle, stays the same. The scene is seeded, so every broadphase sees identical boxes.
+fn circle_field(count: usize) -> Vec<Aabb> {
+    const RADIUS: f32 = 0.5;
+    const AREA_PER_CIRCLE: f32 = 10.0;
+
+    let side = (count as f32 * AREA_PER_CIRCLE).sqrt();
+
+    let mut random = Xorshift::new(0x9E37_79B9);
+    let mut random = || random.next_f32();
+
+    (0..count)
+        .map(|_| {
+            let center = (random() * side, random() * side);
+            Aabb {
+                min: (center.0 - RADIUS, center.1 - RADIUS),
+                max: (center.0 + RADIUS, center.1 + RADIUS),
+            }
+        })
+        .collect()
+}
+
+/// The average time for one call to `find_pairs`, and the number of pairs found
+fn time(broadphase: &mut dyn Broadphase, aabbs: &[Aabb], iterations: u32) -> (Duration, usize) {
+    let mut pairs = Vec::new();
+
+    // Warm up once so persistent state like the sweep order is in place, as it would be after the first step of a simulation
+    broadphase.find_pairs(aabbs, &mut pairs);
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        broadphase.find_pairs(aabbs, &mut pairs);
+    }
+
+    (start.elapsed() / iterations.max(1), pairs.len())
+}
+
+fn main() {
+    let iterations = 10;
+
+    println!("Average time to find candidate pairs over {iterations} runs");
+    for count in [1_000, 10_000, 50_000] {
+        let aabbs = circle_field(count);
+
+        let mut strategies: Vec<Box<dyn Broadphase>> = vec![
+            Box::new(SpatialHashGrid::new(2.0)),
+            Box::new(SweepAndPrune::new()),
+        ];
+
+        // Brute force takes far too long beyond a few thousand circles
+        if count <= 10_000 {
+            strategies.push(Box::new(BruteForce));
+        }
+
+        for strategy in strategies.iter_mut() {
+            let (time, pairs) = time(strategy.as_mut(), &aabbs, iterations);
+            println!(
+                "{:>6} circles  {:<18} {:>10.3?}  {} pairs",
+                count,
+                strategy.name(),
+                time,
+                pairs
+            );
+        }
+    }
+}
@@ -5,16 +5,21 @@ extern crate skia_safe;
 pub mod model;
 pub mod physics;
 pub mod renderer;
+pub mod scene;
 pub mod simulation;
 
-use std::time::Instant;
+use std::io;
+use std::time::{Duration, Instant};
 
-use glfw::{Action, Context, Glfw, Key, WindowEvent, WindowHint};
+use glfw::{Action, Context, Glfw, Key, MouseButton, WindowEvent, WindowHint};
 use skia_safe::Color;
 
 use model::primitive::*;
+use physics::joint::Spring;
 use renderer::Renderer;
-use renderer::SkiaRenderer;
+use renderer::{RasterRenderer, SkiaRenderer, SvgRenderer};
+use scene::Scene;
+use simulation::history::Recording;
 use simulation::Simulation;
 
 struct WindowContext {
@@ -40,7 +45,7 @@ fn create_window() -> WindowContext {
     glfw.default_window_hints();
     glfw.window_hint(WindowHint::Samples(Some(num_samples)));
     glfw.window_hint(WindowHint::StencilBits(Some(stencil_bits)));
-    glfw.window_hint(WindowHint::Resizable(false));
+    glfw.window_hint(WindowHint::Resizable(true));
 
     let (mut window, events) = glfw
         .create_window(x_size, y_size, title, glfw::WindowMode::Windowed)
@@ -48,6 +53,9 @@ fn create_window() -> WindowContext {
 
     window.make_current();
     window.set_key_polling(true);
+    window.set_mouse_button_polling(true);
+    window.set_cursor_pos_polling(true);
+    window.set_framebuffer_size_polling(true);
 
     // Load OpenGL function pointers
     gl::load_with(|symbol| window.get_proc_address(symbol) as *const _);
@@ -61,69 +69,127 @@ fn create_window() -> WindowContext {
     }
 }
 
+/// The scene shown when no scene file is given
+const DEMO_SCENE: &str = include_str!("../scenes/demo.json");
+
+/// Where the current state is saved from the window
+const SAVED_SCENE_PATH: &str = "saved_scene.json";
+
+/// Where the history is saved as a recording from the window
+const RECORDING_PATH: &str = "recording.json";
+
+/// The slowest and fastest time scales reachable with the keyboard
+const MIN_TIME_SCALE: f32 = 1.0 / 16.0;
+const MAX_TIME_SCALE: f32 = 4.0;
+
+/// How many snapshots the window keeps for rewinding, ten seconds at the default physics rate
+const HISTORY_CAPACITY: usize = 1200;
+
+/// How many physics steps each press of the arrow keys moves through the history
+const SCRUB_STEPS: isize = 6;
+
+/// Fill a simulation from the command line
+///
+/// `--replay <path>` plays back a recording, `--scene <path>` loads a scene file, and otherwise the demo scene is used.
+fn load_simulation<R: Renderer>(simulation: &mut Simulation<R>, args: &[String]) {
+    let path_after = |flag: &str| {
+        args.iter()
+            .position(|arg| arg == flag)
+            .and_then(|index| args.get(index + 1))
+    };
+
+    if let Some(path) = path_after("--replay") {
+        let recording =
+            Recording::load(path).unwrap_or_else(|error| exit_with_load_error(path, error));
+        simulation.play_recording(recording);
+        return;
+    }
+
+    let scene = match path_after("--scene") {
+        Some(path) => Scene::load(path).unwrap_or_else(|error| exit_with_load_error(path, error)),
+        None => Scene::from_json(DEMO_SCENE).expect("The demo scene is invalid."),
+    };
+    scene.build(simulation);
+}
+
+fn exit_with_load_error(path: &str, error: impl std::fmt::Display) -> ! {
+    eprintln!("Failed to load {path}: {error}");
+    std::process::exit(1);
+}
+
+/// Run the simulation given on the command line without a window for a number of frames
+///
+/// Frames advance by a fixed 1/60 s, so the output is the same on every run regardless of how fast it renders. `write_error` takes any failure to write the frame just drawn, and the first one stops the run.
+fn render_headless<R: Renderer>(
+    renderer: R,
+    args: &[String],
+    frames: u32,
+    write_error: impl Fn(&mut R) -> Option<io::Error>,
+) {
+    let mut simulation = Simulation::new(renderer);
+    load_simulation(&mut simulation, args);
+
+    let frame_time = Duration::from_secs_f64(1.0 / 60.0);
+    for _ in 0..frames {
+        simulation.update(frame_time);
+        simulation.next_frame();
+
+        if let Some(error) = write_error(&mut simulation.renderer) {
+            eprintln!("Failed to write frame: {error}");
+            std::process::exit(1);
+        }
+    }
+}
+
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+
+    if let Some(flag) = args.iter().position(|arg| arg == "--headless") {
+        let options: Vec<&str> = args[(flag + 1)..]
+            .iter()
+            .take_while(|arg| !arg.starts_with("--"))
+            .map(String::as_str)
+            .collect();
+        let frames = options
+            .first()
+            .and_then(|frames| frames.parse().ok())
+            .unwrap_or(300);
+        let directory = options.get(1).copied().unwrap_or("frames");
+
+        let dimensions = (1024, 1024);
+        if args.iter().any(|arg| arg == "--svg") {
+            let mut renderer = SvgRenderer::new(dimensions);
+            renderer
+                .set_frame_directory(Some(directory.into()))
+                .expect("Failed to create the frame directory.");
+            render_headless(renderer, &args, frames, SvgRenderer::take_write_error);
+        } else {
+            let mut renderer = RasterRenderer::new(dimensions);
+            renderer
+                .set_frame_directory(Some(directory.into()))
+                .expect("Failed to create the frame directory.");
+            render_headless(renderer, &args, frames, RasterRenderer::take_write_error);
+        }
+
+        println!("Wrote {frames} frames to {directory}");
+        return;
+    }
+
     let mut window_context = create_window();
 
     let context_properties = renderer::SurfaceProperties {
-        dimensions: window_context.window.get_size(),
+        dimensions: window_context.window.get_framebuffer_size(),
         num_samples: window_context.samples,
         stencil_bits: window_context.stencil_bits,
     };
-    let mut renderer = SkiaRenderer::new(&context_properties);
-
-    renderer.set_physics_region((0.0, 0.0), (100.0, 100.0));
+    let renderer = SkiaRenderer::new(&context_properties);
 
     let mut simulation = Simulation::new(renderer);
 
     simulation.inputs.view_region_scroll_speed_multiplier = 50.0;
+    simulation.history.set_capacity(HISTORY_CAPACITY);
 
-    // Draw a background rectangle
-    simulation.add_object_with_model(
-        Rectangle {
-            origin: (0.0, 0.0),
-            dimensions: (100.0, 100.0),
-            color: Color::from_rgb(8, 0, 22),
-        }
-        .into(),
-    );
-
-    // Add circles in an L shape to verify the canvas is flipped correctly
-    let d1_body = simulation
-        .add_object_with_model_at_pos(
-            Circle {
-                origin: (0.0, 0.0),
-                radius: 2.0,
-                color: Color::WHITE,
-            }
-            .into(),
-            (25.0, 25.0),
-        )
-        .physics_body;
-    simulation.add_object_with_model_at_pos(
-        Circle {
-            origin: (0.0, 0.0),
-            radius: 2.0,
-            color: Color::WHITE,
-        }
-        .into(),
-        (25.0, 75.0),
-    );
-    simulation.add_object_with_model_at_pos(
-        Circle {
-            origin: (0.0, 0.0),
-            radius: 2.0,
-            color: Color::WHITE,
-        }
-        .into(),
-        (50.0, 25.0),
-    );
-
-    simulation
-        .physics
-        .get_object_mut(d1_body)
-        .unwrap()
-        .dynamics
-        .velocity = (0.0, 1.0);
+    load_simulation(&mut simulation, &args);
 
     let mut last_frame_time = Instant::now();
 
@@ -152,6 +218,18 @@ fn handle_window_event(
     match event {
         WindowEvent::Key(Key::Escape, _, Action::Press, _) => window.set_should_close(true),
 
+        // A minimized window has an empty framebuffer, which cannot back a surface
+        WindowEvent::FramebufferSize(width, height) if width > 0 && height > 0 => {
+            unsafe { gl::Viewport(0, 0, width, height) };
+            simulation.renderer.resize_surface((width, height));
+        }
+
+        // Cycle how the view region is fit to the window
+        WindowEvent::Key(Key::F, _, Action::Press, _) => {
+            let fit = simulation.renderer.get_view_fit().next();
+            simulation.renderer.set_view_fit(fit);
+        }
+
         // Zoom controls
         WindowEvent::Key(Key::Kp9, _, Action::Press, _) => {
             simulation.inputs.view_region_zoom_speed = 1.0
@@ -197,11 +275,106 @@ fn handle_window_event(
 
         // Scroll controls
 
+        // Mouse controls
+        WindowEvent::CursorPos(x, y) => {
+            // The cursor is in window coordinates, which differ from framebuffer pixels on high DPI displays
+            let (window_width, window_height) = window.get_size();
+            let (surface_width, surface_height) = window.get_framebuffer_size();
+            let pixel = (
+                x as f32 * surface_width as f32 / window_width as f32,
+                y as f32 * surface_height as f32 / window_height as f32,
+            );
+            simulation.inputs.cursor_pixel = Some(pixel);
+            simulation.inputs.cursor_position = simulation.renderer.screen_to_physics(pixel);
+        }
+
+        WindowEvent::MouseButton(MouseButton::Left, Action::Press, _) => {
+            simulation.begin_drag();
+        }
+        WindowEvent::MouseButton(MouseButton::Left, Action::Release, _) => simulation.end_drag(),
+
+        // Drop a soft blob into empty space
+        WindowEvent::MouseButton(MouseButton::Right, Action::Press, modifiers)
+            if modifiers.contains(glfw::Modifiers::Shift) =>
+        {
+            let cursor = simulation.inputs.cursor_position;
+            if simulation.object_at_point(cursor).is_none() {
+                let node = Circle {
+                    origin: (0.0, 0.0),
+                    radius: 0.8,
+                    color: Color::from_rgb(120, 230, 160),
+                };
+                let spring = Spring {
+                    stiffness: 300.0,
+                    damping: 2.0,
+                };
+                simulation.add_soft_blob(cursor, 5.0, 16, node.into(), spring, 150.0);
+            }
+        }
+
+        // Drop a new circle into empty space
+        WindowEvent::MouseButton(MouseButton::Right, Action::Press, _) => {
+            let cursor = simulation.inputs.cursor_position;
+            if simulation.object_at_point(cursor).is_none() {
+                simulation.add_object_with_model_at_pos(
+                    Circle {
+                        origin: (0.0, 0.0),
+                        radius: 2.0,
+                        color: Color::from_rgb(90, 170, 255),
+                    }
+                    .into(),
+                    cursor,
+                );
+            }
+        }
+
+        // Show the world bounds
+        WindowEvent::Key(Key::B, _, Action::Press, _) => {
+            simulation.show_bounds = !simulation.show_bounds
+        }
+
         // Reset the view
         WindowEvent::Key(Key::Kp5, _, Action::Release, _) => {
-            simulation
-                .renderer
-                .set_physics_region((0.0, 0.0), (100.0, 100.0));
+            let home = simulation.home_view_region;
+            simulation.set_view_region(home);
+        }
+
+        // Time controls
+        WindowEvent::Key(Key::Space, _, Action::Press, _) => simulation.toggle_pause(),
+        WindowEvent::Key(Key::Period, _, Action::Press | Action::Repeat, _) => {
+            simulation.step_once()
+        }
+        WindowEvent::Key(Key::Comma, _, Action::Press | Action::Repeat, _) => simulation.scrub(-1),
+        WindowEvent::Key(Key::Minus, _, Action::Press, _) => {
+            simulation.time_scale = (simulation.time_scale / 2.0).max(MIN_TIME_SCALE);
+        }
+        WindowEvent::Key(Key::Equal, _, Action::Press, _) => {
+            simulation.time_scale = (simulation.time_scale * 2.0).min(MAX_TIME_SCALE);
+        }
+
+        // Look back through the history
+        WindowEvent::Key(Key::Left, _, Action::Press | Action::Repeat, _) => {
+            simulation.scrub(-SCRUB_STEPS)
+        }
+        WindowEvent::Key(Key::Right, _, Action::Press | Action::Repeat, _) => {
+            simulation.scrub(SCRUB_STEPS)
+        }
+        WindowEvent::Key(Key::Enter, _, Action::Press, _) => simulation.resume(),
+
+        // Save the history so it can be replayed later
+        WindowEvent::Key(Key::R, _, Action::Press, _) => {
+            match simulation.recording().save(RECORDING_PATH) {
+                Ok(()) => println!("Saved the recording to {RECORDING_PATH}"),
+                Err(error) => eprintln!("Failed to save the recording: {error}"),
+            }
+        }
+
+        // Save the current state as a scene
+        WindowEvent::Key(Key::S, _, Action::Press, _) => {
+            match Scene::capture(simulation).save(SAVED_SCENE_PATH) {
+                Ok(()) => println!("Saved the scene to {SAVED_SCENE_PATH}"),
+                Err(error) => eprintln!("Failed to save the scene: {error}"),
+            }
         }
 
         _ => {}
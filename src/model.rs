@@ -2,30 +2,43 @@
 
 pub mod primitive {
 
+    use serde::{Deserialize, Serialize};
     use skia_safe::Color;
 
+    use super::hex_color;
+
     /// A circle with a center origin
+    #[derive(Clone, Serialize, Deserialize)]
     pub struct Circle {
+        #[serde(default)]
         pub origin: (f32, f32),
         pub radius: f32,
+        #[serde(with = "hex_color")]
         pub color: Color,
     }
 
     /// A rectangle with a top-left origin
+    #[derive(Clone, Serialize, Deserialize)]
     pub struct Rectangle {
+        #[serde(default)]
         pub origin: (f32, f32),
         pub dimensions: (f32, f32),
+        #[serde(with = "hex_color")]
         pub color: Color,
     }
 }
 
 use std::convert::From;
 
+use serde::{Deserialize, Serialize};
+
 use primitive::*;
 
 /// A 2D primitive model
 ///
 /// This enum represents the 2D primitive models. More complex models can be created by combining these primitives.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Primitive {
     Circle(Circle),
     Rectangle(Rectangle),
@@ -42,3 +55,42 @@ impl From<Rectangle> for Primitive {
         Primitive::Rectangle(rectangle)
     }
 }
+
+/// Colors as `#rrggbb` strings, or `#rrggbbaa` when they are not opaque
+pub(crate) mod hex_color {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use skia_safe::Color;
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut hex = format!("#{:02x}{:02x}{:02x}", color.r(), color.g(), color.b());
+        if color.a() != 255 {
+            hex.push_str(&format!("{:02x}", color.a()));
+        }
+        serializer.serialize_str(&hex)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let hex = String::deserialize(deserializer)?;
+
+        let digits = hex
+            .strip_prefix('#')
+            .filter(|digits| digits.len() == 6 || digits.len() == 8)
+            .ok_or_else(|| {
+                de::Error::custom(format!("expected #rrggbb or #rrggbbaa, got {hex}"))
+            })?;
+        let channel = |i: usize| {
+            digits
+                .get(i..(i + 2))
+                .and_then(|channel| u8::from_str_radix(channel, 16).ok())
+                .ok_or_else(|| de::Error::custom(format!("invalid color {hex}")))
+        };
+
+        let alpha = if digits.len() == 8 { channel(6)? } else { 255 };
+        Ok(Color::from_argb(
+            alpha,
+            channel(0)?,
+            channel(2)?,
+            channel(4)?,
+        ))
+    }
+}
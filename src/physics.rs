@@ -1,58 +1,456 @@
 //! Physics engine
 
+pub mod bounds;
+pub mod broadphase;
+pub mod ccd;
+pub mod collision;
+pub mod force_field;
+pub mod gravitation;
+pub mod integrator;
+pub mod joint;
+pub mod material;
+// Only the tests and benchmarks draw random numbers, not the engine itself
+#[allow(dead_code)]
+pub mod random;
+pub mod shape;
+pub mod slot_map;
+pub mod solver;
+pub mod spring_network;
+pub mod vector;
+
+pub use shape::{Aabb, Capsule, Circle, MassProperties, Polygon, Rectangle, Segment, Shape};
+
+use std::collections::HashSet;
 use std::time::Duration;
 
+use serde::{Deserialize, Serialize};
+
+use bounds::{Containment, WorldBounds};
+use broadphase::{Broadphase, SweepAndPrune};
+use force_field::{BuiltinField, ForceField};
+use gravitation::NBodyGravity;
+use integrator::{Acceleration, BuiltinIntegrator, Integrator, SemiImplicitEuler, State};
+use joint::{Joint, JointConstraint, JointId, JointKind};
+use material::{CombineRule, Material};
+use slot_map::{Key, SlotMap};
+use solver::{ContactCache, SolverSettings};
+use spring_network::{ResolvedNetwork, SpringNetwork, SpringNetworkId};
+
 /// A position and orientation in 2D space
-#[derive(Default)]
+#[derive(Default, Clone, Copy, Serialize, Deserialize)]
 pub struct Pose {
     pub position: (f32, f32),
     pub orientation: f32,
 }
 
+impl Pose {
+    /// Linearly interpolate toward another pose. An `alpha` of 0.0 gives this pose and 1.0 gives `other`.
+    pub fn interpolate(&self, other: &Pose, alpha: f32) -> Pose {
+        Pose {
+            position: vector::add(
+                self.position,
+                vector::scale(vector::sub(other.position, self.position), alpha),
+            ),
+            orientation: self.orientation + (other.orientation - self.orientation) * alpha,
+        }
+    }
+}
+
 /// Movement properties of a physical object
-#[derive(Default)]
+///
+/// Mass and inertia are kept alongside their inverses since the inverses are what the engine uses. A static body has infinite mass and inertia, so both inverses are zero and no force or impulse can move it.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Dynamics {
     pub velocity: (f32, f32),
+    /// Angular velocity in radians per second, counter-clockwise positive
+    pub angular_velocity: f32,
+
+    /// Force accumulated since the last update
+    pub force: (f32, f32),
+    /// Torque accumulated since the last update
+    pub torque: f32,
+
+    #[serde(with = "infinite_as_null")]
+    mass: f32,
+    inverse_mass: f32,
+    #[serde(with = "infinite_as_null")]
+    inertia: f32,
+    inverse_inertia: f32,
 }
 
-/// A physics circle primitive
-pub struct Circle {
-    pub origin: (f32, f32),
-    pub radius: f32,
+impl Default for Dynamics {
+    fn default() -> Dynamics {
+        Dynamics {
+            velocity: (0.0, 0.0),
+            angular_velocity: 0.0,
+            force: (0.0, 0.0),
+            torque: 0.0,
+            mass: 1.0,
+            inverse_mass: 1.0,
+            inertia: 1.0,
+            inverse_inertia: 1.0,
+        }
+    }
+}
+
+impl Dynamics {
+    pub fn mass(&self) -> f32 {
+        self.mass
+    }
+
+    pub fn inverse_mass(&self) -> f32 {
+        self.inverse_mass
+    }
+
+    /// Moment of inertia about the body position
+    pub fn inertia(&self) -> f32 {
+        self.inertia
+    }
+
+    pub fn inverse_inertia(&self) -> f32 {
+        self.inverse_inertia
+    }
+
+    /// Set the mass. A mass of zero or infinity makes the body immovable by forces.
+    pub fn set_mass(&mut self, mass: f32) {
+        self.mass = mass;
+        self.inverse_mass = inverse_or_zero(mass);
+    }
+
+    /// Set the moment of inertia. An inertia of zero or infinity prevents forces from rotating the body.
+    pub fn set_inertia(&mut self, inertia: f32) {
+        self.inertia = inertia;
+        self.inverse_inertia = inverse_or_zero(inertia);
+    }
+
+    /// Make the body immovable scenery with infinite mass and inertia
+    pub fn set_static(&mut self) {
+        self.set_mass(f32::INFINITY);
+        self.set_inertia(f32::INFINITY);
+        self.velocity = (0.0, 0.0);
+        self.angular_velocity = 0.0;
+    }
+
+    /// Static bodies cannot be moved by forces or impulses
+    pub fn is_static(&self) -> bool {
+        self.inverse_mass == 0.0 && self.inverse_inertia == 0.0
+    }
+}
+
+/// Infinite mass properties are written as `null`, since JSON has no infinity
+mod infinite_as_null {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(value: &f32, serializer: S) -> Result<S::Ok, S::Error> {
+        if value.is_infinite() {
+            serializer.serialize_none()
+        } else {
+            serializer.serialize_some(value)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+        Ok(Option::<f32>::deserialize(deserializer)?.unwrap_or(f32::INFINITY))
+    }
+}
+
+/// The reciprocal of a mass property, treating zero and infinity as immovable
+fn inverse_or_zero(value: f32) -> f32 {
+    if value > 0.0 && value.is_finite() {
+        1.0 / value
+    } else {
+        0.0
+    }
 }
 
 /// A unique identifier for a body in the physics engine
 ///
-/// Each body in the physics engine has a unique numeric ID. The wrapper type prevents accidental mixing of IDs from different systems or inadvertent arithmetic operations.
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
-pub struct BodyId(usize);
+/// Each body in the physics engine has a unique generational handle. The wrapper type prevents accidental mixing of IDs from different systems or inadvertent arithmetic operations. Once a body is removed its ID never refers to another body, even if the storage is reused.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BodyId(Key);
 
 /// One physical body in the physics simulation
 ///
 /// Bodies in the simulation represent physical objects that can move and collide. Typically a game object will have a graphics model and a physics body.
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Body {
     pub id: BodyId,
     pub pose: Pose,
+    /// The pose before the most recent update, used to interpolate between physics steps when drawing
+    pub previous_pose: Pose,
     pub dynamics: Dynamics,
 
-    pub circle: Circle,
+    pub shape: Shape,
+
+    /// What the body is made of. Change it with `set_material` to keep the mass in step with the density.
+    #[serde(default)]
+    pub material: Material,
+
+    /// Whether the body takes part in collisions. Scenery such as backgrounds should disable this.
+    pub collides: bool,
+
+    /// Whether the body is swept along its path each update so it cannot pass through thin obstacles, which only applies to circles
+    ///
+    /// Sweeping costs a test against every other colliding body, so it is best kept for small, fast bodies like projectiles.
+    #[serde(default)]
+    pub bullet: bool,
+}
+
+impl Body {
+    /// Move the body without interpolating from where it was
+    pub fn set_position(&mut self, position: (f32, f32)) {
+        self.pose.position = position;
+        self.previous_pose.position = position;
+    }
+
+    /// Change what the body is made of, recomputing its mass and inertia from the new density
+    ///
+    /// Static bodies stay static.
+    pub fn set_material(&mut self, material: Material) {
+        self.material = material;
+        if self.dynamics.is_static() {
+            return;
+        }
+
+        let mass_properties = self.shape.mass_properties(material.density);
+        self.dynamics.set_mass(mass_properties.mass);
+        self.dynamics.set_inertia(mass_properties.inertia);
+    }
+
+    /// The velocity of a point attached to the body, including rotation. The point is in world coordinates.
+    pub fn velocity_at_point(&self, point: (f32, f32)) -> (f32, f32) {
+        vector::add(
+            self.dynamics.velocity,
+            vector::cross_scalar(
+                self.dynamics.angular_velocity,
+                vector::sub(point, self.pose.position),
+            ),
+        )
+    }
+
+    /// Apply a force through the body position. It acts until the next update.
+    pub fn apply_force(&mut self, force: (f32, f32)) {
+        self.dynamics.force = vector::add(self.dynamics.force, force);
+    }
+
+    /// Apply a force at a point in world coordinates. Forces off the body position also produce torque.
+    pub fn apply_force_at_point(&mut self, force: (f32, f32), point: (f32, f32)) {
+        self.apply_force(force);
+        self.dynamics.torque += vector::cross(vector::sub(point, self.pose.position), force);
+    }
+
+    /// Apply an instantaneous change in momentum through the body position
+    pub fn apply_impulse(&mut self, impulse: (f32, f32)) {
+        self.dynamics.velocity = vector::add(
+            self.dynamics.velocity,
+            vector::scale(impulse, self.dynamics.inverse_mass),
+        );
+    }
+
+    /// Apply an instantaneous change in momentum at a point in world coordinates
+    pub fn apply_impulse_at_point(&mut self, impulse: (f32, f32), point: (f32, f32)) {
+        self.apply_impulse(impulse);
+        self.dynamics.angular_velocity += self.dynamics.inverse_inertia
+            * vector::cross(vector::sub(point, self.pose.position), impulse);
+    }
+}
+
+/// A copy of the complete state of the physics engine
+///
+/// Restoring a snapshot returns every body, joint and spring network to exactly the state it had, including IDs and materials, so stepping from a restored snapshot gives bit-for-bit the same results as the first time. The integrator, broadphase, solver settings, forces and combine rules are settings rather than state and are not included.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PhysicsSnapshot {
+    objects: SlotMap<Body>,
+    #[serde(default)]
+    joints: SlotMap<Joint>,
+    #[serde(default)]
+    spring_networks: SlotMap<SpringNetwork>,
+    #[serde(default)]
+    contact_cache: ContactCache,
+}
+
+/// Everything about how a physics engine simulates, as opposed to the state of its bodies
+///
+/// Settings can be saved alongside snapshots so a simulation carries on the same way after loading. Custom force fields and integrators cannot be saved and are left out.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct PhysicsSettings {
+    /// The integration scheme, or `None` to keep whatever the engine has
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub integrator: Option<BuiltinIntegrator>,
+    #[serde(default)]
+    pub gravity: (f32, f32),
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub force_fields: Vec<BuiltinField>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_body_gravity: Option<NBodyGravity>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<WorldBounds>,
+    #[serde(default)]
+    pub restitution_combine: CombineRule,
+    #[serde(default)]
+    pub friction_combine: CombineRule,
+    #[serde(default)]
+    pub solver: SolverSettings,
 }
 
 /// The root of the physics engine
 ///
 /// The physics engine updates object states based on motion and collisions.
 pub struct PhysicsEngine {
-    objects: Vec<Body>,
+    objects: SlotMap<Body>,
+
+    joints: SlotMap<Joint>,
+
+    spring_networks: SlotMap<SpringNetwork>,
+
+    integrator: Box<dyn Integrator>,
+
+    broadphase: Box<dyn Broadphase>,
+
+    force_fields: Vec<Box<dyn ForceField>>,
+
+    /// Acceleration applied to every non-static body, in units per second squared
+    pub gravity: (f32, f32),
+
+    /// Mutual attraction between every pair of non-static bodies, off by default
+    pub n_body_gravity: Option<NBodyGravity>,
+
+    /// The region bodies are kept within, unbounded by default
+    pub bounds: Option<WorldBounds>,
+
+    /// Bodies removed by the bounds since they were last taken
+    despawned: Vec<BodyId>,
+
+    /// How the restitution of two touching materials is combined
+    pub restitution_combine: CombineRule,
+
+    /// How the friction coefficients of two touching materials are combined
+    pub friction_combine: CombineRule,
+
+    /// How contacts are resolved
+    pub solver: SolverSettings,
+
+    /// Contact impulses from the last update, for warm starting the solver
+    contact_cache: ContactCache,
+}
+
+impl Default for PhysicsEngine {
+    fn default() -> PhysicsEngine {
+        PhysicsEngine::new()
+    }
 }
 
 impl PhysicsEngine {
     /// Create a new physics engine
     pub fn new() -> PhysicsEngine {
         PhysicsEngine {
-            objects: Vec::new(),
+            objects: SlotMap::new(),
+            joints: SlotMap::new(),
+            spring_networks: SlotMap::new(),
+            integrator: Box::new(SemiImplicitEuler),
+            broadphase: Box::new(SweepAndPrune::new()),
+            force_fields: Vec::new(),
+            gravity: (0.0, 0.0),
+            n_body_gravity: None,
+            bounds: None,
+            despawned: Vec::new(),
+            restitution_combine: CombineRule::default(),
+            friction_combine: CombineRule::default(),
+            solver: SolverSettings::default(),
+            contact_cache: ContactCache::default(),
         }
     }
 
+    /// The integration scheme used to advance bodies
+    pub fn integrator(&self) -> &dyn Integrator {
+        self.integrator.as_ref()
+    }
+
+    /// Replace the integration scheme used to advance bodies
+    pub fn set_integrator(&mut self, integrator: Box<dyn Integrator>) {
+        self.integrator = integrator;
+    }
+
+    /// The strategy used to find candidate pairs for collision tests
+    pub fn broadphase(&self) -> &dyn Broadphase {
+        self.broadphase.as_ref()
+    }
+
+    /// Replace the strategy used to find candidate pairs for collision tests
+    pub fn set_broadphase(&mut self, broadphase: Box<dyn Broadphase>) {
+        self.broadphase = broadphase;
+    }
+
+    /// Add a force field acting on every non-static body
+    pub fn add_force_field(&mut self, field: Box<dyn ForceField>) {
+        self.force_fields.push(field);
+    }
+
+    /// The force fields acting on bodies, in the order they were added
+    pub fn force_fields(&self) -> &[Box<dyn ForceField>] {
+        &self.force_fields
+    }
+
+    pub fn clear_force_fields(&mut self) {
+        self.force_fields.clear();
+    }
+
+    /// Copy the engine's settings, leaving out custom force fields and integrators
+    pub fn settings(&self) -> PhysicsSettings {
+        PhysicsSettings {
+            integrator: self.integrator.to_builtin(),
+            gravity: self.gravity,
+            force_fields: self
+                .force_fields
+                .iter()
+                .filter_map(|field| field.to_builtin())
+                .collect(),
+            n_body_gravity: self.n_body_gravity,
+            bounds: self.bounds,
+            restitution_combine: self.restitution_combine,
+            friction_combine: self.friction_combine,
+            solver: self.solver,
+        }
+    }
+
+    /// Replace the engine's settings, including every force field
+    ///
+    /// The integrator is only replaced if the settings have one.
+    pub fn apply_settings(&mut self, settings: &PhysicsSettings) {
+        if let Some(integrator) = settings.integrator {
+            self.integrator = Box::new(integrator);
+        }
+        self.gravity = settings.gravity;
+        self.force_fields = settings
+            .force_fields
+            .iter()
+            .map(|&field| Box::new(field) as Box<dyn ForceField>)
+            .collect();
+        self.n_body_gravity = settings.n_body_gravity;
+        self.bounds = settings.bounds;
+        self.restitution_combine = settings.restitution_combine;
+        self.friction_combine = settings.friction_combine;
+        self.solver = settings.solver;
+    }
+
+    /// Copy the state of every body
+    pub fn snapshot(&self) -> PhysicsSnapshot {
+        PhysicsSnapshot {
+            objects: self.objects.clone(),
+            joints: self.joints.clone(),
+            spring_networks: self.spring_networks.clone(),
+            contact_cache: self.contact_cache.clone(),
+        }
+    }
+
+    /// Return every body to the state captured in a snapshot, replacing all current bodies, joints and spring networks
+    pub fn restore(&mut self, snapshot: &PhysicsSnapshot) {
+        self.objects = snapshot.objects.clone();
+        self.joints = snapshot.joints.clone();
+        self.spring_networks = snapshot.spring_networks.clone();
+        self.contact_cache = snapshot.contact_cache.clone();
+    }
+
     pub fn get_object(&self, id: BodyId) -> Option<&Body> {
         self.objects.get(id.0)
     }
@@ -62,21 +460,482 @@ impl PhysicsEngine {
     }
 
     /// Add a new object to the physics engine
-    pub fn add_object(&mut self, circle: Circle) -> &mut Body {
-        self.objects.push(Body {
-            id: BodyId(self.objects.len()),
-            pose: Pose::default(),
-            dynamics: Dynamics::default(),
-            circle,
+    ///
+    /// The body is made of the default material, and its mass and inertia come from its shape with that density. Shapes without area, like segments, give an immovable body.
+    ///
+    /// Bodies turn about their position, so the shape is moved to put its center of mass on the body position, and the body starts where that center was. A shape centered on its own origin is left as it is and the body starts at the origin.
+    pub fn add_object(&mut self, shape: impl Into<Shape>) -> &mut Body {
+        let mut shape = shape.into();
+
+        let material = Material::default();
+        let mass_properties = shape.mass_properties(material.density);
+        let mut dynamics = Dynamics::default();
+        dynamics.set_mass(mass_properties.mass);
+        dynamics.set_inertia(mass_properties.inertia);
+
+        shape.translate(vector::scale(mass_properties.center, -1.0));
+        let pose = Pose {
+            position: mass_properties.center,
+            orientation: 0.0,
+        };
+
+        let key = self.objects.insert_with(|key| Body {
+            id: BodyId(key),
+            pose,
+            previous_pose: pose,
+            dynamics,
+            shape,
+            material,
+            collides: true,
+            bullet: false,
         });
-        self.objects.last_mut().unwrap()
+        self.objects.get_mut(key).unwrap()
+    }
+
+    /// Remove a body from the physics engine, returning it if it existed
+    ///
+    /// The body's ID becomes invalid, so later lookups with it return `None`. Joints attached to the body are removed with it, as are its springs.
+    pub fn remove_object(&mut self, id: BodyId) -> Option<Body> {
+        let attached: Vec<JointId> = self
+            .joints
+            .values()
+            .iter()
+            .filter(|joint| joint.a == id || joint.b == id)
+            .map(|joint| joint.id)
+            .collect();
+        for joint in attached {
+            self.joints.remove(joint.0);
+        }
+        for network in self.spring_networks.values_mut() {
+            network.remove_body(id);
+        }
+
+        self.objects.remove(id.0)
+    }
+
+    /// All bodies in the engine, in no particular order
+    pub fn objects(&self) -> &[Body] {
+        self.objects.values()
+    }
+
+    /// Connect two bodies with a joint
+    ///
+    /// The anchors are where the joint attaches to each body, in world coordinates, and stay fixed to their bodies from then on. Returns `None` if either body does not exist or both are the same body.
+    pub fn add_joint(
+        &mut self,
+        a: BodyId,
+        b: BodyId,
+        anchor_a: (f32, f32),
+        anchor_b: (f32, f32),
+        kind: JointKind,
+    ) -> Option<&mut Joint> {
+        if a == b {
+            return None;
+        }
+        let body_a = self.objects.get(a.0)?;
+        let body_b = self.objects.get(b.0)?;
+
+        let key = self
+            .joints
+            .insert_with(|key| Joint::new(JointId(key), body_a, body_b, anchor_a, anchor_b, kind));
+        self.joints.get_mut(key)
+    }
+
+    /// Remove a joint, returning it if it existed
+    pub fn remove_joint(&mut self, id: JointId) -> Option<Joint> {
+        self.joints.remove(id.0)
+    }
+
+    pub fn get_joint(&self, id: JointId) -> Option<&Joint> {
+        self.joints.get(id.0)
+    }
+
+    pub fn get_joint_mut(&mut self, id: JointId) -> Option<&mut Joint> {
+        self.joints.get_mut(id.0)
+    }
+
+    /// All joints in the engine, in no particular order
+    pub fn joints(&self) -> &[Joint] {
+        self.joints.values()
+    }
+
+    /// Add a network of springs between bodies
+    pub fn add_spring_network(&mut self, network: SpringNetwork) -> SpringNetworkId {
+        SpringNetworkId(self.spring_networks.insert_with(|_| network))
+    }
+
+    /// Remove a spring network, returning it if it existed. Its bodies stay in the engine.
+    pub fn remove_spring_network(&mut self, id: SpringNetworkId) -> Option<SpringNetwork> {
+        self.spring_networks.remove(id.0)
+    }
+
+    pub fn get_spring_network(&self, id: SpringNetworkId) -> Option<&SpringNetwork> {
+        self.spring_networks.get(id.0)
+    }
+
+    pub fn get_spring_network_mut(&mut self, id: SpringNetworkId) -> Option<&mut SpringNetwork> {
+        self.spring_networks.get_mut(id.0)
+    }
+
+    /// All spring networks in the engine, in no particular order
+    pub fn spring_networks(&self) -> &[SpringNetwork] {
+        self.spring_networks.values()
     }
 
     /// Update the physics engine state
     pub fn update(&mut self, dt: Duration) {
-        for object in self.objects.iter_mut() {
-            object.pose.position.0 += object.dynamics.velocity.0 * dt.as_secs_f32();
-            object.pose.position.1 += object.dynamics.velocity.1 * dt.as_secs_f32();
+        let dt = dt.as_secs_f32();
+
+        for object in self.objects.values_mut().iter_mut() {
+            object.previous_pose = object.pose;
+        }
+
+        let mut states: Vec<State> = self
+            .objects
+            .values()
+            .iter()
+            .map(|object| State {
+                position: object.pose.position,
+                velocity: object.dynamics.velocity,
+                orientation: object.pose.orientation,
+                angular_velocity: object.dynamics.angular_velocity,
+            })
+            .collect();
+
+        // Accumulated forces are held constant over the step
+        let applied: Vec<Acceleration> = self
+            .objects
+            .values()
+            .iter()
+            .map(|object| Acceleration {
+                linear: vector::scale(object.dynamics.force, object.dynamics.inverse_mass),
+                angular: object.dynamics.torque * object.dynamics.inverse_inertia,
+            })
+            .collect();
+
+        let masses: Vec<(f32, f32)> = self
+            .objects
+            .values()
+            .iter()
+            .map(|object| (object.dynamics.mass, object.dynamics.inverse_mass))
+            .collect();
+
+        // Static bodies have infinite mass, so they are left out of mutual attraction entirely
+        let attracting_masses: Vec<f32> = masses
+            .iter()
+            .map(|&(mass, inverse_mass)| if inverse_mass == 0.0 { 0.0 } else { mass })
+            .collect();
+
+        let objects = &self.objects;
+        let spring_networks: Vec<ResolvedNetwork> = self
+            .spring_networks
+            .values()
+            .iter()
+            .map(|network| network.resolve(|id| objects.dense_index(id.0)))
+            .collect();
+
+        // Gravity, force fields, mutual attraction and springs depend on the state, so they are evaluated at every state the integrator asks about
+        let gravity = self.gravity;
+        let force_fields = &self.force_fields;
+        let n_body_gravity = self.n_body_gravity;
+        let acceleration = |states: &[State], accelerations: &mut [Acceleration]| {
+            let mut mutual = vec![(0.0, 0.0); states.len()];
+            if let Some(n_body_gravity) = n_body_gravity {
+                let positions: Vec<(f32, f32)> =
+                    states.iter().map(|state| state.position).collect();
+                n_body_gravity.accelerations(&positions, &attracting_masses, &mut mutual);
+            }
+
+            let mut spring_forces = vec![(0.0, 0.0); states.len()];
+            for network in &spring_networks {
+                network.accumulate_forces(states, &mut spring_forces);
+            }
+
+            for (i, (state, acceleration)) in
+                states.iter().zip(accelerations.iter_mut()).enumerate()
+            {
+                *acceleration = applied[i];
+
+                let (mass, inverse_mass) = masses[i];
+                if inverse_mass == 0.0 {
+                    continue;
+                }
+
+                let field_force = force_fields.iter().fold(spring_forces[i], |total, field| {
+                    vector::add(total, field.force(state.position, state.velocity, mass))
+                });
+                acceleration.linear = vector::add(
+                    acceleration.linear,
+                    vector::add(gravity, vector::scale(field_force, inverse_mass)),
+                );
+                acceleration.linear = vector::add(acceleration.linear, mutual[i]);
+            }
+        };
+
+        self.integrator.integrate(&mut states, dt, &acceleration);
+
+        for (object, state) in self.objects.values_mut().iter_mut().zip(states.iter()) {
+            object.pose.position = state.position;
+            object.pose.orientation = state.orientation;
+            object.dynamics.velocity = state.velocity;
+            object.dynamics.angular_velocity = state.angular_velocity;
+
+            object.dynamics.force = (0.0, 0.0);
+            object.dynamics.torque = 0.0;
+        }
+
+        self.advance_bullets(dt);
+        self.resolve_collisions(dt);
+        self.apply_bounds();
+    }
+
+    /// Keep every non-static body within the world bounds
+    fn apply_bounds(&mut self) {
+        let Some(bounds) = self.bounds else {
+            return;
+        };
+
+        let mut despawned = Vec::new();
+        for object in self.objects.values_mut().iter_mut() {
+            if object.dynamics.is_static() {
+                continue;
+            }
+            if bounds.contain(object) == Containment::Despawned {
+                despawned.push(object.id);
+            }
+        }
+
+        for id in despawned {
+            self.remove_object(id);
+            self.despawned.push(id);
+        }
+    }
+
+    /// Take the IDs of the bodies the bounds have removed since the last call
+    ///
+    /// Anything tracking bodies, like game objects, should drop the ones listed here.
+    pub fn take_despawned(&mut self) -> Vec<BodyId> {
+        std::mem::take(&mut self.despawned)
+    }
+
+    /// Move each bullet back to the first thing it hit on its way this update, bounce it off, and send it on for the rest of the update
+    ///
+    /// Other bodies are taken to be where they ended up. A bullet that keeps hitting things is left touching the last one after a few bounces, and the rest of its motion this update is lost.
+    fn advance_bullets(&mut self, dt: f32) {
+        let objects = self.objects.values_mut();
+        for i in 0..objects.len() {
+            let bullet = &objects[i];
+            let Shape::Circle(circle) = &bullet.shape else {
+                continue;
+            };
+            if !bullet.bullet || !bullet.collides || bullet.dynamics.is_static() {
+                continue;
+            }
+
+            // Sweeping a slightly smaller circle leaves the bullet overlapping what it hits a little, so the contact solver sees the contact
+            let radius = circle.radius - ccd::BULLET_OVERLAP;
+            let offset = vector::rotate(circle.origin, bullet.pose.orientation);
+            let mut from = bullet.previous_pose.position;
+            let mut remaining = dt;
+            for bounce in 0..=ccd::MAX_BULLET_BOUNCES {
+                let to = objects[i].pose.position;
+                let displacement = vector::sub(to, from);
+                let Some((j, t)) =
+                    ccd::first_impact(objects, i, vector::add(from, offset), displacement, radius)
+                else {
+                    break;
+                };
+
+                objects[i].pose.position = vector::add(from, vector::scale(displacement, t));
+                if bounce == ccd::MAX_BULLET_BOUNCES {
+                    break;
+                }
+
+                // Only velocities change here, since the bullet has already been put where it hits
+                let (a, b) = (i.min(j), i.max(j));
+                let Some(manifold) = collision::collide(
+                    &objects[a].shape,
+                    &objects[a].pose,
+                    &objects[b].shape,
+                    &objects[b].pose,
+                ) else {
+                    break;
+                };
+                let settings = SolverSettings {
+                    warm_starting: false,
+                    position_correction: solver::PositionCorrection::Baumgarte,
+                    correction_factor: 0.0,
+                    ..self.solver
+                };
+                solver::solve(
+                    objects,
+                    vec![(a, b, manifold)],
+                    &mut [],
+                    &mut ContactCache::default(),
+                    &settings,
+                    self.restitution_combine,
+                    self.friction_combine,
+                    dt,
+                );
+
+                remaining *= 1.0 - t;
+                from = objects[i].pose.position;
+                objects[i].pose.position =
+                    vector::add(from, vector::scale(objects[i].dynamics.velocity, remaining));
+            }
+        }
+    }
+
+    /// Find all overlapping bodies and resolve their contacts together with the joints
+    ///
+    /// The broadphase narrows the search to bodies with overlapping bounds before the exact shape tests run.
+    fn resolve_collisions(&mut self, dt: f32) {
+        let objects_by_id = &self.objects;
+        let joint_bodies: Vec<(usize, usize)> = self
+            .joints
+            .values()
+            .iter()
+            .map(|joint| {
+                // Joints are removed with their bodies, so both always exist
+                let a = objects_by_id.dense_index(joint.a.0).unwrap();
+                let b = objects_by_id.dense_index(joint.b.0).unwrap();
+                (a, b)
+            })
+            .collect();
+
+        // Linked bodies usually overlap at their anchors, so they only collide if the joint asks for it
+        let connected: HashSet<(usize, usize)> = self
+            .joints
+            .values()
+            .iter()
+            .zip(&joint_bodies)
+            .filter(|(joint, _)| !joint.collide_connected)
+            .map(|(_, &(a, b))| (a.min(b), a.max(b)))
+            .collect();
+
+        // Bodies that do not collide never need testing
+        let objects = self.objects.values_mut();
+
+        let candidates: Vec<usize> = (0..objects.len())
+            .filter(|&i| objects[i].collides)
+            .collect();
+        let bounds: Vec<Aabb> = candidates
+            .iter()
+            .map(|&i| objects[i].shape.aabb(&objects[i].pose))
+            .collect();
+
+        let mut pairs = Vec::new();
+        self.broadphase.find_pairs(&bounds, &mut pairs);
+
+        // Resolve in a fixed order so results do not depend on the broadphase in use
+        let mut pairs: Vec<(usize, usize)> = pairs
+            .into_iter()
+            .map(|(i, j)| (candidates[i], candidates[j]))
+            .collect();
+        pairs.sort_unstable();
+
+        let contacts: Vec<(usize, usize, collision::Manifold)> = pairs
+            .into_iter()
+            .filter_map(|(i, j)| {
+                let (a, b) = (&objects[i], &objects[j]);
+                if a.dynamics.is_static() && b.dynamics.is_static() || connected.contains(&(i, j)) {
+                    return None;
+                }
+                collision::collide(&a.shape, &a.pose, &b.shape, &b.pose)
+                    .map(|manifold| (i, j, manifold))
+            })
+            .collect();
+
+        let mut joints: Vec<JointConstraint> = self
+            .joints
+            .values()
+            .iter()
+            .zip(&joint_bodies)
+            .map(|(joint, &(a, b))| {
+                joint.prepare(
+                    objects,
+                    a,
+                    b,
+                    self.solver.warm_starting,
+                    self.solver.correction_factor,
+                    dt,
+                )
+            })
+            .collect();
+
+        solver::solve(
+            objects,
+            contacts,
+            &mut joints,
+            &mut self.contact_cache,
+            &self.solver,
+            self.restitution_combine,
+            self.friction_combine,
+            dt,
+        );
+
+        for (joint, constraint) in self.joints.values_mut().iter_mut().zip(&joints) {
+            joint.store_impulses(constraint);
         }
     }
 }
+
+/// Borrow two different bodies mutably at the same time
+fn pair_mut(objects: &mut [Body], i: usize, j: usize) -> (&mut Body, &mut Body) {
+    debug_assert!(i < j);
+    let (left, right) = objects.split_at_mut(j);
+    (&mut left[i], &mut right[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use joint::Spring;
+
+    #[test]
+    fn removing_a_body_removes_its_joints_and_springs() {
+        let mut engine = PhysicsEngine::new();
+        let ids: Vec<BodyId> = (0..3)
+            .map(|i| {
+                let body = engine.add_object(Circle {
+                    origin: (0.0, 0.0),
+                    radius: 0.5,
+                });
+                body.set_position((i as f32 * 2.0, 0.0));
+                body.id
+            })
+            .collect();
+        let (a, b, c) = (ids[0], ids[1], ids[2]);
+
+        let kind = JointKind::Revolute {
+            limits: None,
+            motor: None,
+        };
+        engine
+            .add_joint(a, b, (1.0, 0.0), (1.0, 0.0), kind)
+            .unwrap();
+        let kept = engine
+            .add_joint(a, c, (2.0, 0.0), (2.0, 0.0), kind)
+            .unwrap()
+            .id;
+
+        let spring = Spring {
+            stiffness: 100.0,
+            damping: 1.0,
+        };
+        let rope = SpringNetwork::rope(&engine, &ids, spring);
+        let rope = engine.add_spring_network(rope);
+
+        engine.remove_object(b);
+
+        assert!(engine.get_object(b).is_none());
+        assert_eq!(engine.joints().len(), 1);
+        assert!(engine.get_joint(kept).is_some());
+        let rope = engine.get_spring_network(rope).unwrap();
+        assert!(rope.bodies().iter().all(|&body| body != b));
+        assert!(rope.links.is_empty());
+
+        // The engine keeps updating with the remaining bodies
+        engine.update(Duration::from_secs_f32(1.0 / 60.0));
+    }
+}
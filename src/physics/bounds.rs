@@ -0,0 +1,229 @@
+//! Edges that keep bodies inside a region of the world
+
+use serde::{Deserialize, Serialize};
+
+use super::Body;
+
+/// What happens to a body that reaches one edge of the world bounds
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EdgeBehavior {
+    /// A solid wall the body bounces off, keeping this fraction of its speed into the wall
+    Wall { restitution: f32 },
+    /// The body leaves through this edge and comes back in through the opposite one
+    Wrap,
+    /// The body is removed from the engine once it is completely past the edge
+    Despawn,
+}
+
+/// A rectangular region bodies are kept within, with its own behavior on each edge
+///
+/// Bounds act on the bounding box of every non-static body, except for wrapping, which moves a body once its position crosses the edge. When only one of two opposite edges wraps, a body leaving through it comes back in against the other edge, which pushes it inside.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct WorldBounds {
+    /// The bottom-left corner
+    pub min: (f32, f32),
+    /// The top-right corner
+    pub max: (f32, f32),
+
+    pub left: EdgeBehavior,
+    pub right: EdgeBehavior,
+    pub bottom: EdgeBehavior,
+    pub top: EdgeBehavior,
+}
+
+/// One side of the world bounds
+pub struct Edge {
+    pub behavior: EdgeBehavior,
+    pub from: (f32, f32),
+    pub to: (f32, f32),
+}
+
+/// The result of keeping one body within the bounds
+#[derive(PartialEq)]
+pub(super) enum Containment {
+    Inside,
+    Despawned,
+}
+
+impl WorldBounds {
+    /// Bounds with the same behavior on every edge
+    pub fn new(min: (f32, f32), max: (f32, f32), behavior: EdgeBehavior) -> WorldBounds {
+        WorldBounds {
+            min,
+            max,
+            left: behavior,
+            right: behavior,
+            bottom: behavior,
+            top: behavior,
+        }
+    }
+
+    /// Each edge as a line with its behavior, for drawing
+    pub fn edges(&self) -> [Edge; 4] {
+        let (min, max) = (self.min, self.max);
+        let edge = |behavior, from, to| Edge { behavior, from, to };
+        [
+            edge(self.left, min, (min.0, max.1)),
+            edge(self.right, (max.0, min.1), max),
+            edge(self.bottom, min, (max.0, min.1)),
+            edge(self.top, (min.0, max.1), max),
+        ]
+    }
+
+    /// Apply each edge's behavior to a body that has moved past it
+    pub(super) fn contain(&self, body: &mut Body) -> Containment {
+        let aabb = body.shape.aabb(&body.pose);
+
+        let axes = [
+            (0, self.left, self.right, aabb.min.0, aabb.max.0),
+            (1, self.bottom, self.top, aabb.min.1, aabb.max.1),
+        ];
+        for (axis, low_edge, high_edge, aabb_min, aabb_max) in axes {
+            let (low, high) = if axis == 0 {
+                (self.min.0, self.max.0)
+            } else {
+                (self.min.1, self.max.1)
+            };
+            let position = component_mut(&mut body.pose.position, axis);
+
+            if aabb_max < low && low_edge == EdgeBehavior::Despawn
+                || aabb_min > high && high_edge == EdgeBehavior::Despawn
+            {
+                return Containment::Despawned;
+            }
+
+            // Move the previous pose along too so the body does not streak across the screen when interpolated
+            let wrap = if *position < low && low_edge == EdgeBehavior::Wrap {
+                high - low
+            } else if *position > high && high_edge == EdgeBehavior::Wrap {
+                low - high
+            } else {
+                0.0
+            };
+            *position += wrap;
+            *component_mut(&mut body.previous_pose.position, axis) += wrap;
+
+            // A body wrapped onto a wall still has to be pushed off of it
+            let (aabb_min, aabb_max) = (aabb_min + wrap, aabb_max + wrap);
+
+            let velocity = component_mut(&mut body.dynamics.velocity, axis);
+            if let EdgeBehavior::Wall { restitution } = low_edge {
+                if aabb_min < low {
+                    *position += low - aabb_min;
+                    if *velocity < 0.0 {
+                        *velocity *= -restitution;
+                    }
+                }
+            }
+            if let EdgeBehavior::Wall { restitution } = high_edge {
+                if aabb_max > high {
+                    *position -= aabb_max - high;
+                    if *velocity > 0.0 {
+                        *velocity *= -restitution;
+                    }
+                }
+            }
+        }
+
+        Containment::Inside
+    }
+}
+
+fn component_mut(vector: &mut (f32, f32), axis: usize) -> &mut f32 {
+    if axis == 0 {
+        &mut vector.0
+    } else {
+        &mut vector.1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Circle, PhysicsEngine};
+    use super::*;
+
+    /// A 10 by 10 region from the origin
+    fn bounds(behavior: EdgeBehavior) -> WorldBounds {
+        WorldBounds::new((0.0, 0.0), (10.0, 10.0), behavior)
+    }
+
+    /// An engine holding one unit circle at a position, moving with a velocity
+    fn ball(position: (f32, f32), velocity: (f32, f32)) -> PhysicsEngine {
+        let mut engine = PhysicsEngine::new();
+        let body = engine.add_object(Circle {
+            origin: (0.0, 0.0),
+            radius: 1.0,
+        });
+        body.set_position(position);
+        body.dynamics.velocity = velocity;
+        engine
+    }
+
+    fn contain(bounds: &WorldBounds, engine: &mut PhysicsEngine) -> (Containment, Body) {
+        let body = &mut engine.objects.values_mut()[0];
+        (bounds.contain(body), body.clone())
+    }
+
+    #[test]
+    fn wall_pushes_the_body_back_and_bounces_it() {
+        let walls = bounds(EdgeBehavior::Wall { restitution: 0.5 });
+
+        let mut engine = ball((-0.5, 5.0), (-4.0, 1.0));
+        let (containment, body) = contain(&walls, &mut engine);
+        assert!(containment == Containment::Inside);
+        assert_eq!(body.pose.position, (1.0, 5.0));
+        assert_eq!(body.dynamics.velocity, (2.0, 1.0));
+
+        // Already moving away, it keeps its speed
+        let mut engine = ball((9.5, 9.5), (-3.0, -2.0));
+        let (_, body) = contain(&walls, &mut engine);
+        assert_eq!(body.pose.position, (9.0, 9.0));
+        assert_eq!(body.dynamics.velocity, (-3.0, -2.0));
+    }
+
+    #[test]
+    fn wrap_moves_the_pose_and_the_previous_pose() {
+        let mut engine = ball((10.5, -0.25), (3.0, -3.0));
+        engine.objects.values_mut()[0].previous_pose.position = (10.25, 0.0);
+
+        let (containment, body) = contain(&bounds(EdgeBehavior::Wrap), &mut engine);
+        assert!(containment == Containment::Inside);
+        assert_eq!(body.pose.position, (0.5, 9.75));
+        assert_eq!(body.previous_pose.position, (0.25, 10.0));
+        assert_eq!(body.dynamics.velocity, (3.0, -3.0));
+
+        // Overlapping the edge is not enough to wrap
+        let mut engine = ball((9.5, 5.0), (3.0, 0.0));
+        let (_, body) = contain(&bounds(EdgeBehavior::Wrap), &mut engine);
+        assert_eq!(body.pose.position, (9.5, 5.0));
+    }
+
+    #[test]
+    fn despawn_waits_until_the_body_is_fully_past_the_edge() {
+        let despawn = bounds(EdgeBehavior::Despawn);
+        for (x, expected) in [
+            (-0.5, Containment::Inside),
+            (-0.99, Containment::Inside),
+            (-1.01, Containment::Despawned),
+            (11.01, Containment::Despawned),
+        ] {
+            let mut engine = ball((x, 5.0), (0.0, 0.0));
+            assert!(contain(&despawn, &mut engine).0 == expected, "at {x}");
+        }
+    }
+
+    #[test]
+    fn wrapping_onto_a_wall_pushes_the_body_inside() {
+        let bounds = WorldBounds {
+            left: EdgeBehavior::Wrap,
+            right: EdgeBehavior::Wall { restitution: 1.0 },
+            ..bounds(EdgeBehavior::Wall { restitution: 1.0 })
+        };
+
+        let mut engine = ball((-0.25, 5.0), (-2.0, 0.0));
+        let (_, body) = contain(&bounds, &mut engine);
+        assert_eq!(body.pose.position, (9.0, 5.0));
+        assert_eq!(body.dynamics.velocity, (-2.0, 0.0));
+    }
+}
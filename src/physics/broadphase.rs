@@ -0,0 +1,288 @@
+//! Broadphase collision culling
+//!
+//! Testing every pair of bodies is O(n²). A broadphase quickly finds the pairs whose bounding boxes overlap so the exact shape tests only run on those candidates.
+
+use super::shape::Aabb;
+
+/// A strategy for finding pairs of overlapping bounding boxes
+pub trait Broadphase {
+    /// A short human-readable name
+    fn name(&self) -> &'static str;
+
+    /// Find every pair of overlapping boxes
+    ///
+    /// Pairs are indices into `aabbs` with the smaller index first. Existing contents of `pairs` are replaced. The order of the pairs is unspecified.
+    fn find_pairs(&mut self, aabbs: &[Aabb], pairs: &mut Vec<(usize, usize)>);
+}
+
+/// Tests every pair of boxes. Only suitable for small scenes, but useful as a reference.
+pub struct BruteForce;
+
+impl Broadphase for BruteForce {
+    fn name(&self) -> &'static str {
+        "Brute force"
+    }
+
+    fn find_pairs(&mut self, aabbs: &[Aabb], pairs: &mut Vec<(usize, usize)>) {
+        pairs.clear();
+        for i in 0..aabbs.len() {
+            for j in (i + 1)..aabbs.len() {
+                if aabbs[i].overlaps(&aabbs[j]) {
+                    pairs.push((i, j));
+                }
+            }
+        }
+    }
+}
+
+/// The most cells a box can be placed in before it is tested against every other box instead
+const MAX_CELLS_PER_BOX: i64 = 64;
+
+/// A uniform grid where each box is placed in every cell it touches
+///
+/// Works best when bodies are similar in size and the cell size is a little larger than a typical body. Boxes covering more than a few dozen cells, like floors, are kept out of the grid and tested against every other box, so each one costs as much as a body under brute force.
+pub struct SpatialHashGrid {
+    cell_size: f32,
+
+    /// Cell coordinates and box index for every cell a box touches, reused between calls
+    entries: Vec<((i32, i32), usize)>,
+
+    /// Boxes too large to place in the grid, reused between calls
+    oversized: Vec<usize>,
+}
+
+impl SpatialHashGrid {
+    /// Create a grid with square cells of the given side length, which must be positive and finite
+    pub fn new(cell_size: f32) -> SpatialHashGrid {
+        assert!(
+            cell_size > 0.0 && cell_size.is_finite(),
+            "the cell size of a spatial hash grid must be positive and finite, not {cell_size}"
+        );
+
+        SpatialHashGrid {
+            cell_size,
+            entries: Vec::new(),
+            oversized: Vec::new(),
+        }
+    }
+
+    pub fn cell_size(&self) -> f32 {
+        self.cell_size
+    }
+
+    fn cell(&self, point: (f32, f32)) -> (i32, i32) {
+        (
+            (point.0 / self.cell_size).floor() as i32,
+            (point.1 / self.cell_size).floor() as i32,
+        )
+    }
+}
+
+impl Broadphase for SpatialHashGrid {
+    fn name(&self) -> &'static str {
+        "Spatial hash grid"
+    }
+
+    fn find_pairs(&mut self, aabbs: &[Aabb], pairs: &mut Vec<(usize, usize)>) {
+        pairs.clear();
+
+        let mut entries = std::mem::take(&mut self.entries);
+        entries.clear();
+        self.oversized.clear();
+
+        for (index, aabb) in aabbs.iter().enumerate() {
+            let min = self.cell(aabb.min);
+            let max = self.cell(aabb.max);
+
+            // Widen before multiplying, since cells far from the origin saturate at the limits of i32
+            let columns = max.0 as i64 - min.0 as i64 + 1;
+            let rows = max.1 as i64 - min.1 as i64 + 1;
+            if columns.saturating_mul(rows) > MAX_CELLS_PER_BOX {
+                self.oversized.push(index);
+                continue;
+            }
+
+            for x in min.0..=max.0 {
+                for y in min.1..=max.1 {
+                    entries.push(((x, y), index));
+                }
+            }
+        }
+
+        // Sorting groups the entries of each cell together, standing in for a hash map
+        entries.sort_unstable();
+
+        for run in entries.chunk_by(|a, b| a.0 == b.0) {
+            let cell = run[0].0;
+            for (n, &(_, i)) in run.iter().enumerate() {
+                for &(_, j) in run[(n + 1)..].iter() {
+                    let (a, b) = (&aabbs[i], &aabbs[j]);
+                    if !a.overlaps(b) {
+                        continue;
+                    }
+
+                    // Boxes can share several cells. Only report the pair from the cell holding the corner where their overlap begins.
+                    let overlap_start = self.cell((a.min.0.max(b.min.0), a.min.1.max(b.min.1)));
+                    if overlap_start == cell {
+                        pairs.push((i.min(j), i.max(j)));
+                    }
+                }
+            }
+        }
+
+        // Oversized boxes meet every box, skipping pairs of oversized boxes already tested the other way around
+        for (n, &i) in self.oversized.iter().enumerate() {
+            for (j, other) in aabbs.iter().enumerate() {
+                if j == i || self.oversized[..n].contains(&j) {
+                    continue;
+                }
+                if aabbs[i].overlaps(other) {
+                    pairs.push((i.min(j), i.max(j)));
+                }
+            }
+        }
+
+        self.entries = entries;
+    }
+}
+
+/// Sorts boxes along the x axis and sweeps across them, only testing boxes whose x extents overlap
+///
+/// The sort order is kept between calls. Bodies move little from one step to the next, so the order is nearly sorted already and re-sorting it is cheap.
+#[derive(Default)]
+pub struct SweepAndPrune {
+    order: Vec<usize>,
+}
+
+impl SweepAndPrune {
+    pub fn new() -> SweepAndPrune {
+        SweepAndPrune::default()
+    }
+}
+
+impl Broadphase for SweepAndPrune {
+    fn name(&self) -> &'static str {
+        "Sweep and prune"
+    }
+
+    fn find_pairs(&mut self, aabbs: &[Aabb], pairs: &mut Vec<(usize, usize)>) {
+        pairs.clear();
+
+        if self.order.len() != aabbs.len() {
+            self.order = (0..aabbs.len()).collect();
+        }
+        self.order
+            .sort_by(|&a, &b| aabbs[a].min.0.total_cmp(&aabbs[b].min.0));
+
+        for (n, &i) in self.order.iter().enumerate() {
+            let a = &aabbs[i];
+            for &j in self.order[(n + 1)..].iter() {
+                let b = &aabbs[j];
+
+                // Everything further along starts after this box ends
+                if b.min.0 > a.max.0 {
+                    break;
+                }
+
+                if a.min.1 <= b.max.1 && b.min.1 <= a.max.1 {
+                    pairs.push((i.min(j), i.max(j)));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::random::Xorshift;
+    use super::*;
+
+    /// Boxes of assorted sizes scattered on both sides of the origin, so some cover many grid cells and some straddle cell borders at negative coordinates
+    fn scattered_boxes() -> Vec<Aabb> {
+        let mut random = Xorshift::new(0x2545_F491);
+        let mut random = || random.next_f32();
+
+        (0..400)
+            .map(|_| {
+                let center = (random() * 60.0 - 30.0, random() * 60.0 - 30.0);
+                let half_extents = (random() * random() * 4.0, random() * 1.5);
+                Aabb {
+                    min: (center.0 - half_extents.0, center.1 - half_extents.1),
+                    max: (center.0 + half_extents.0, center.1 + half_extents.1),
+                }
+            })
+            .collect()
+    }
+
+    fn sorted_pairs(broadphase: &mut dyn Broadphase, aabbs: &[Aabb]) -> Vec<(usize, usize)> {
+        let mut pairs = vec![(0, 0)];
+        broadphase.find_pairs(aabbs, &mut pairs);
+        pairs.sort_unstable();
+        pairs
+    }
+
+    #[test]
+    fn strategies_agree_with_brute_force() {
+        let mut aabbs = scattered_boxes();
+        let expected = sorted_pairs(&mut BruteForce, &aabbs);
+        assert!(expected.len() > 100, "too few overlaps to compare");
+
+        let mut strategies: Vec<Box<dyn Broadphase>> = vec![
+            Box::new(SpatialHashGrid::new(2.0)),
+            Box::new(SpatialHashGrid::new(0.3)),
+            Box::new(SweepAndPrune::new()),
+        ];
+        for strategy in strategies.iter_mut() {
+            assert_eq!(
+                sorted_pairs(strategy.as_mut(), &aabbs),
+                expected,
+                "{}",
+                strategy.name()
+            );
+        }
+
+        // Strategies that keep state between calls must still agree once the boxes move
+        for aabb in aabbs.iter_mut() {
+            aabb.min.0 -= 3.0;
+            aabb.max.0 -= 3.0;
+        }
+        let expected = sorted_pairs(&mut BruteForce, &aabbs);
+        for strategy in strategies.iter_mut() {
+            assert_eq!(sorted_pairs(strategy.as_mut(), &aabbs), expected);
+        }
+    }
+
+    #[test]
+    fn grid_handles_huge_boxes() {
+        let mut aabbs = scattered_boxes();
+        aabbs.push(Aabb {
+            min: (-1e30, -1e30),
+            max: (1e30, 1e30),
+        });
+        aabbs.push(Aabb {
+            min: (-10.0, -0.5),
+            max: (10.0, 0.5),
+        });
+        aabbs.push(Aabb {
+            min: (f32::MIN, 2.0),
+            max: (f32::MAX, 2.5),
+        });
+
+        // Each huge box would otherwise cover far more cells than could ever be visited
+        let expected = sorted_pairs(&mut BruteForce, &aabbs);
+        let pairs = sorted_pairs(&mut SpatialHashGrid::new(0.3), &aabbs);
+        assert_eq!(pairs, expected);
+    }
+
+    #[test]
+    #[should_panic]
+    fn zero_cell_size_is_rejected() {
+        SpatialHashGrid::new(0.0);
+    }
+
+    #[test]
+    #[should_panic]
+    fn negative_cell_size_is_rejected() {
+        SpatialHashGrid::new(-1.0);
+    }
+}
@@ -0,0 +1,314 @@
+//! Continuous collision detection for fast bodies
+//!
+//! Contacts are normally found only where bodies end up after each update, so a small body moving further than its own size in one update can pass straight through a thin obstacle without ever being seen to overlap it. Bodies flagged as bullets are instead swept along their path, and stopped at the first thing they would hit.
+
+use super::collision::closest_point_on_segment;
+use super::shape::{Core, Shape};
+use super::{vector, Aabb, Body, Pose};
+
+/// How far a bullet is left overlapping the first thing it hits, which is enough for the contact solver to see the contact but too little for position correction to push on
+pub(super) const BULLET_OVERLAP: f32 = 0.005;
+
+/// How many times a bullet can bounce within one update before it is left where it hit
+pub(super) const MAX_BULLET_BOUNCES: u32 = 3;
+
+/// How far along a path a moving circle first touches a shape, as a fraction from 0.0 at the start to 1.0 at the end
+///
+/// Returns `None` if the circle never touches the shape, or if it already overlaps the shape at the start, where it is left to the contact solver.
+pub fn time_of_impact(
+    start: (f32, f32),
+    displacement: (f32, f32),
+    radius: f32,
+    shape: &Shape,
+    pose: &Pose,
+) -> Option<f32> {
+    swept_circle(start, displacement, radius, &shape.core(pose))
+}
+
+/// Sweep a circle against a core, which is the same as casting a ray from the circle's center against the core grown by the circle's radius
+pub(super) fn swept_circle(
+    start: (f32, f32),
+    displacement: (f32, f32),
+    radius: f32,
+    core: &Core,
+) -> Option<f32> {
+    let radius = core.radius + radius;
+    if overlaps(core, start, radius) {
+        return None;
+    }
+
+    let count = core.vertices.len();
+    let edges = if count == 2 { 1 } else { count };
+    if count == 1 {
+        return ray_circle(start, displacement, core.vertices[0], radius);
+    }
+
+    // Outside the core the grown shape is the union of a capsule around each edge
+    (0..edges)
+        .filter_map(|i| {
+            let a = core.vertices[i];
+            let b = core.vertices[(i + 1) % count];
+            ray_capsule(start, displacement, a, b, radius)
+        })
+        .min_by(f32::total_cmp)
+}
+
+/// Whether a point is within a distance of a core
+fn overlaps(core: &Core, point: (f32, f32), radius: f32) -> bool {
+    if core.vertices.len() == 1 {
+        return vector::length_squared(vector::sub(point, core.vertices[0])) <= radius * radius;
+    }
+
+    let inside_core = core.vertices.len() > 2
+        && core
+            .vertices
+            .iter()
+            .zip(core.normals.iter())
+            .all(|(&vertex, &normal)| vector::dot(normal, vector::sub(point, vertex)) <= 0.0);
+    if inside_core {
+        return true;
+    }
+
+    (0..core.vertices.len()).any(|i| {
+        let a = core.vertices[i];
+        let b = core.vertices[(i + 1) % core.vertices.len()];
+        let closest = closest_point_on_segment(point, a, b);
+        vector::length_squared(vector::sub(point, closest)) <= radius * radius
+    })
+}
+
+/// Where a ray starting outside a circle first enters it, as a fraction of the ray
+fn ray_circle(
+    start: (f32, f32),
+    displacement: (f32, f32),
+    center: (f32, f32),
+    radius: f32,
+) -> Option<f32> {
+    // Solve |start + t * displacement - center|² = radius² for the smaller root
+    let offset = vector::sub(start, center);
+    let a = vector::length_squared(displacement);
+    if a <= f32::EPSILON {
+        return None;
+    }
+    let b = vector::dot(offset, displacement);
+    let c = vector::length_squared(offset) - radius * radius;
+
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+
+    let t = (-b - discriminant.sqrt()) / a;
+    (0.0..=1.0).contains(&t).then_some(t)
+}
+
+/// Where a ray starting outside a capsule first enters it, as a fraction of the ray
+fn ray_capsule(
+    start: (f32, f32),
+    displacement: (f32, f32),
+    a: (f32, f32),
+    b: (f32, f32),
+    radius: f32,
+) -> Option<f32> {
+    let caps = [a, b]
+        .into_iter()
+        .filter_map(|center| ray_circle(start, displacement, center, radius));
+
+    let edge = vector::sub(b, a);
+    let normal = vector::scale(vector::normalize((-edge.1, edge.0)), radius);
+    if normal == (0.0, 0.0) {
+        return caps.min_by(f32::total_cmp);
+    }
+
+    // The flat sides are the edge moved out by the radius either way
+    let sides = [normal, vector::scale(normal, -1.0)]
+        .into_iter()
+        .filter_map(|offset| {
+            ray_segment(
+                start,
+                displacement,
+                vector::add(a, offset),
+                vector::add(b, offset),
+            )
+        });
+
+    caps.chain(sides).min_by(f32::total_cmp)
+}
+
+/// Where a ray crosses a segment, as a fraction of the ray
+fn ray_segment(
+    start: (f32, f32),
+    displacement: (f32, f32),
+    a: (f32, f32),
+    b: (f32, f32),
+) -> Option<f32> {
+    let edge = vector::sub(b, a);
+    let denominator = vector::cross(displacement, edge);
+    if denominator.abs() <= f32::EPSILON {
+        return None;
+    }
+
+    let offset = vector::sub(a, start);
+    let t = vector::cross(offset, edge) / denominator;
+    let along_edge = vector::cross(offset, displacement) / denominator;
+    ((0.0..=1.0).contains(&t) && (0.0..=1.0).contains(&along_edge)).then_some(t)
+}
+
+/// The first body a bullet's circle would hit moving along a path, and how far along the path it hits
+///
+/// Only bodies whose bounds meet the bounds of the whole path are swept against.
+pub(super) fn first_impact(
+    objects: &[Body],
+    bullet: usize,
+    start: (f32, f32),
+    displacement: (f32, f32),
+    radius: f32,
+) -> Option<(usize, f32)> {
+    let end = vector::add(start, displacement);
+    let path = Aabb {
+        min: (start.0.min(end.0) - radius, start.1.min(end.1) - radius),
+        max: (start.0.max(end.0) + radius, start.1.max(end.1) + radius),
+    };
+
+    objects
+        .iter()
+        .enumerate()
+        .filter(|&(j, other)| j != bullet && other.collides)
+        .filter(|(_, other)| other.shape.aabb(&other.pose).overlaps(&path))
+        .filter_map(|(j, other)| {
+            time_of_impact(start, displacement, radius, &other.shape, &other.pose).map(|t| (j, t))
+        })
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::{Circle, PhysicsEngine, Segment};
+    use super::*;
+
+    /// A vertical segment from (0, -1) to (0, 1)
+    fn wall() -> Shape {
+        Shape::Segment(Segment {
+            start: (0.0, -1.0),
+            end: (0.0, 1.0),
+        })
+    }
+
+    fn assert_hits(time: Option<f32>, expected: f32) {
+        let time = time.expect("the circle should hit");
+        assert!(
+            (time - expected).abs() < 1e-5,
+            "expected {expected}, got {time}"
+        );
+    }
+
+    #[test]
+    fn circle_hits_segment_side() {
+        let time = time_of_impact((-5.0, 0.0), (10.0, 0.0), 0.5, &wall(), &Pose::default());
+        assert_hits(time, 0.45);
+    }
+
+    #[test]
+    fn circle_hits_segment_end() {
+        // Passing just above the end, the circle touches the rounded end rather than the side
+        let time = time_of_impact((-5.0, 1.3), (10.0, 0.0), 0.5, &wall(), &Pose::default());
+        assert_hits(time, 0.46);
+
+        // Moving along the segment's line, it meets the end head on
+        let time = time_of_impact((0.0, 5.0), (0.0, -10.0), 0.5, &wall(), &Pose::default());
+        assert_hits(time, 0.35);
+    }
+
+    #[test]
+    fn circle_hits_rotated_and_moved_shapes() {
+        let pose = Pose {
+            position: (3.0, 0.0),
+            orientation: std::f32::consts::FRAC_PI_2,
+        };
+        let time = time_of_impact((3.0, -5.0), (0.0, 10.0), 0.5, &wall(), &pose);
+        assert_hits(time, 0.45);
+
+        let circle = Shape::Circle(Circle {
+            origin: (0.0, 0.0),
+            radius: 1.0,
+        });
+        let time = time_of_impact((5.0, 0.0), (-10.0, 0.0), 0.5, &circle, &Pose::default());
+        assert_hits(time, 0.35);
+    }
+
+    #[test]
+    fn circle_that_misses_has_no_impact() {
+        // Passing beyond the end
+        let time = time_of_impact((-5.0, 1.6), (10.0, 0.0), 0.5, &wall(), &Pose::default());
+        assert_eq!(time, None);
+
+        // Stopping short
+        let time = time_of_impact((-5.0, 0.0), (4.0, 0.0), 0.5, &wall(), &Pose::default());
+        assert_eq!(time, None);
+
+        // Moving away
+        let time = time_of_impact((-5.0, 0.0), (-10.0, 0.0), 0.5, &wall(), &Pose::default());
+        assert_eq!(time, None);
+    }
+
+    #[test]
+    fn overlapping_start_has_no_impact() {
+        let time = time_of_impact((0.2, 0.0), (10.0, 0.0), 0.5, &wall(), &Pose::default());
+        assert_eq!(time, None);
+
+        let square = Shape::Polygon(super::super::Polygon::new_box((0.0, 0.0), (1.0, 1.0)));
+        let time = time_of_impact((0.0, 0.0), (10.0, 0.0), 0.1, &square, &Pose::default());
+        assert_eq!(time, None);
+    }
+
+    /// Fire a circle of radius 0.05 at a wall with no thickness 5 units away, stepping at the given rate for one second, and give the furthest it ever got along the x axis
+    fn fire_at_thin_wall(speed: f32, bullet: bool, updates_per_second: u32) -> f32 {
+        let mut engine = PhysicsEngine::new();
+
+        let wall = engine.add_object(wall());
+        wall.dynamics.set_static();
+
+        let ball = engine.add_object(Circle {
+            origin: (0.0, 0.0),
+            radius: 0.05,
+        });
+        ball.set_position((-5.0, 0.0));
+        ball.dynamics.velocity = (speed, 0.0);
+        ball.bullet = bullet;
+        let ball = ball.id;
+
+        let dt = Duration::from_secs_f32(1.0 / updates_per_second as f32);
+        let mut furthest = f32::MIN;
+        for _ in 0..updates_per_second {
+            engine.update(dt);
+            furthest = furthest.max(engine.get_object(ball).unwrap().pose.position.0);
+        }
+        furthest
+    }
+
+    #[test]
+    fn bullet_never_passes_a_thin_wall() {
+        for updates_per_second in [30, 60] {
+            for speed in [10.0, 100.0, 1000.0, 10000.0] {
+                let furthest = fire_at_thin_wall(speed, true, updates_per_second);
+                assert!(
+                    furthest < 0.0,
+                    "bullet at {speed} reached {furthest} at {updates_per_second} updates per second"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn fast_plain_circle_tunnels_through_a_thin_wall() {
+        for speed in [100.0, 1000.0, 10000.0] {
+            let furthest = fire_at_thin_wall(speed, false, 30);
+            assert!(furthest > 0.0, "circle at {speed} stopped at {furthest}");
+        }
+
+        // Slow enough to be caught overlapping the wall
+        assert!(fire_at_thin_wall(10.0, false, 30) < 0.0);
+    }
+}
@@ -0,0 +1,531 @@
+//! Collision detection between physics shapes
+//!
+//! Every shape is reduced to a convex core swept by a radius (see `shape::Core`). Cores with edges are tested with the Separating Axis Theorem. When they overlap, the edge with the least penetration becomes the reference face and the most opposed edge of the other core is clipped against it, giving up to two contact points.
+
+use serde::{Deserialize, Serialize};
+
+use super::shape::{Core, Shape};
+use super::vector;
+use super::Pose;
+
+/// Which features of two shapes produced a contact point
+///
+/// The same pair of features gives the same ID from one update to the next while the shapes stay in contact, so results for a contact can be carried over between updates.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default, Serialize, Deserialize)]
+pub struct FeatureId(u32);
+
+impl FeatureId {
+    /// A contact found by clipping, identified by the reference and incident edges, which end of the clipped edge it is, and which shape held the reference edge
+    fn clipped(
+        reference_edge: usize,
+        incident_edge: usize,
+        end: usize,
+        flipped: bool,
+    ) -> FeatureId {
+        FeatureId(
+            (reference_edge as u32 & 0xff)
+                | (incident_edge as u32 & 0xff) << 8
+                | (end as u32 & 1) << 16
+                | (flipped as u32) << 17,
+        )
+    }
+
+    /// The only contact between two shapes that touch at a single point, identified by the nearest edge if there is one
+    fn single(edge: Option<usize>) -> FeatureId {
+        FeatureId(1 << 18 | edge.map_or(0xff, |edge| edge as u32 & 0xff))
+    }
+}
+
+/// A point where two shapes touch
+pub struct ContactPoint {
+    /// The contact point in world coordinates, halfway through the overlap
+    pub point: (f32, f32),
+    /// How far the shapes overlap along the normal at this point
+    pub penetration: f32,
+    pub id: FeatureId,
+}
+
+/// The set of contact points between two overlapping shapes
+pub struct Manifold {
+    /// Unit vector pointing from the first shape toward the second
+    pub normal: (f32, f32),
+    /// One or two contact points
+    pub points: Vec<ContactPoint>,
+}
+
+impl Manifold {
+    /// Swap the roles of the two shapes
+    fn flipped(mut self) -> Manifold {
+        self.normal = vector::scale(self.normal, -1.0);
+        self
+    }
+}
+
+/// Test two shapes for overlap
+///
+/// Returns `None` if the shapes do not overlap. The manifold normal points from `a` toward `b`.
+pub fn collide(a: &Shape, pose_a: &Pose, b: &Shape, pose_b: &Pose) -> Option<Manifold> {
+    let a = a.core(pose_a);
+    let b = b.core(pose_b);
+
+    match (a.vertices.len(), b.vertices.len()) {
+        (1, 1) => point_point(&a, &b),
+        (_, 1) => core_point(&a, &b),
+        (1, _) => core_point(&b, &a).map(Manifold::flipped),
+        _ => core_core(&a, &b),
+    }
+}
+
+/// The contact point halfway between the surfaces of two cores, given the closest points of the cores and the normal from `a` to `b`
+fn midpoint_between_surfaces(
+    a: &Core,
+    point_a: (f32, f32),
+    b: &Core,
+    point_b: (f32, f32),
+    normal: (f32, f32),
+) -> (f32, f32) {
+    let surface_a = vector::add(point_a, vector::scale(normal, a.radius));
+    let surface_b = vector::sub(point_b, vector::scale(normal, b.radius));
+    vector::scale(vector::add(surface_a, surface_b), 0.5)
+}
+
+/// Test two single point cores, which are circles, for overlap
+fn point_point(a: &Core, b: &Core) -> Option<Manifold> {
+    let center_a = a.vertices[0];
+    let center_b = b.vertices[0];
+
+    let offset = vector::sub(center_b, center_a);
+    let radii = a.radius + b.radius;
+
+    let distance_squared = vector::length_squared(offset);
+    if distance_squared >= radii * radii {
+        return None;
+    }
+
+    let distance = distance_squared.sqrt();
+
+    // Concentric circles have no meaningful direction, so pick one consistently to keep results deterministic
+    let normal = if distance > f32::EPSILON {
+        vector::scale(offset, 1.0 / distance)
+    } else {
+        (0.0, 1.0)
+    };
+
+    Some(Manifold {
+        normal,
+        points: vec![ContactPoint {
+            point: midpoint_between_surfaces(a, center_a, b, center_b, normal),
+            penetration: radii - distance,
+            id: FeatureId::single(None),
+        }],
+    })
+}
+
+/// Test a core with edges against a single point core for overlap
+///
+/// The manifold normal points from `core` toward `point`.
+fn core_point(core: &Core, point: &Core) -> Option<Manifold> {
+    let center = point.vertices[0];
+    let radii = core.radius + point.radius;
+
+    // Find the edge the center is furthest in front of
+    let (edge, separation) = (0..core.vertices.len())
+        .map(|i| {
+            (
+                i,
+                vector::dot(core.normals[i], vector::sub(center, core.vertices[i])),
+            )
+        })
+        .fold((0, f32::MIN), |best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        });
+
+    if separation > radii {
+        return None;
+    }
+
+    // With the center inside the core, push out through the nearest edge
+    if separation <= 0.0 {
+        let normal = core.normals[edge];
+        let on_edge = vector::sub(center, vector::scale(normal, separation));
+        return Some(Manifold {
+            normal,
+            points: vec![ContactPoint {
+                point: midpoint_between_surfaces(core, on_edge, point, center, normal),
+                penetration: radii - separation,
+                id: FeatureId::single(Some(edge)),
+            }],
+        });
+    }
+
+    // Otherwise the closest point is on the edge or one of its end vertices
+    let v1 = core.vertices[edge];
+    let v2 = core.vertices[(edge + 1) % core.vertices.len()];
+    let closest = closest_point_on_segment(center, v1, v2);
+
+    let offset = vector::sub(center, closest);
+    let distance_squared = vector::length_squared(offset);
+    if distance_squared >= radii * radii {
+        return None;
+    }
+
+    let distance = distance_squared.sqrt();
+    let normal = if distance > f32::EPSILON {
+        vector::scale(offset, 1.0 / distance)
+    } else {
+        core.normals[edge]
+    };
+
+    Some(Manifold {
+        normal,
+        points: vec![ContactPoint {
+            point: midpoint_between_surfaces(core, closest, point, center, normal),
+            penetration: radii - distance,
+            id: FeatureId::single(Some(edge)),
+        }],
+    })
+}
+
+/// The point on the segment from `a` to `b` closest to `point`
+pub(crate) fn closest_point_on_segment(
+    point: (f32, f32),
+    a: (f32, f32),
+    b: (f32, f32),
+) -> (f32, f32) {
+    let edge = vector::sub(b, a);
+    let length_squared = vector::length_squared(edge);
+    if length_squared <= f32::EPSILON {
+        return a;
+    }
+
+    let t = (vector::dot(vector::sub(point, a), edge) / length_squared).clamp(0.0, 1.0);
+    vector::add(a, vector::scale(edge, t))
+}
+
+/// The edge of `core` with the largest separation from `other`, along with that separation
+///
+/// A positive separation means this edge is a separating axis of the cores. Radii are not included.
+fn max_separation(core: &Core, other: &Core) -> (usize, f32) {
+    let mut best = (0, f32::MIN);
+
+    for (i, (&normal, &vertex)) in core.normals.iter().zip(core.vertices.iter()).enumerate() {
+        // The deepest point of the other core along this normal
+        let separation = other
+            .vertices
+            .iter()
+            .map(|&v| vector::dot(normal, vector::sub(v, vertex)))
+            .fold(f32::MAX, f32::min);
+
+        if separation > best.1 {
+            best = (i, separation);
+        }
+    }
+
+    best
+}
+
+/// The index of the edge of `core` whose normal is most opposed to `normal`
+fn incident_edge(core: &Core, normal: (f32, f32)) -> usize {
+    let mut edge = 0;
+    let mut min_dot = f32::MAX;
+    for (i, &n) in core.normals.iter().enumerate() {
+        let d = vector::dot(n, normal);
+        if d < min_dot {
+            min_dot = d;
+            edge = i;
+        }
+    }
+
+    edge
+}
+
+/// The closest pair of points between two separated cores, along with their distance
+///
+/// For separated convex shapes the closest pair always includes a vertex of one of them.
+fn closest_points(a: &Core, b: &Core) -> ((f32, f32), (f32, f32), f32) {
+    let mut best = (a.vertices[0], b.vertices[0], f32::MAX);
+
+    let mut check = |point_a: (f32, f32), point_b: (f32, f32)| {
+        let distance = vector::length(vector::sub(point_b, point_a));
+        if distance < best.2 {
+            best = (point_a, point_b, distance);
+        }
+    };
+
+    for i in 0..b.vertices.len() {
+        let (v1, v2) = (b.vertices[i], b.vertices[(i + 1) % b.vertices.len()]);
+        for &vertex in a.vertices.iter() {
+            check(vertex, closest_point_on_segment(vertex, v1, v2));
+        }
+    }
+    for i in 0..a.vertices.len() {
+        let (v1, v2) = (a.vertices[i], a.vertices[(i + 1) % a.vertices.len()]);
+        for &vertex in b.vertices.iter() {
+            check(closest_point_on_segment(vertex, v1, v2), vertex);
+        }
+    }
+
+    best
+}
+
+/// Test two cores with edges for overlap
+fn core_core(a: &Core, b: &Core) -> Option<Manifold> {
+    let radii = a.radius + b.radius;
+
+    let (edge_a, separation_a) = max_separation(a, b);
+    if separation_a > radii {
+        return None;
+    }
+
+    let (edge_b, separation_b) = max_separation(b, a);
+    if separation_b > radii {
+        return None;
+    }
+
+    // Prefer the first core as the reference unless the second is clearly better, which keeps contacts stable frame to frame
+    const RELATIVE_TOLERANCE: f32 = 0.95;
+    const ABSOLUTE_TOLERANCE: f32 = 0.01;
+    let flip = separation_b > RELATIVE_TOLERANCE * separation_a + ABSOLUTE_TOLERANCE;
+
+    let (reference, incident, reference_edge, separation) = if flip {
+        (b, a, edge_b, separation_b)
+    } else {
+        (a, b, edge_a, separation_a)
+    };
+
+    // Rounded cores can touch without overlapping. If the closest points are not straight across the reference face, such as the end of a capsule against a corner, they touch at a single point.
+    if separation > 0.0 {
+        let (point_a, point_b, distance) = closest_points(a, b);
+        if distance >= radii {
+            return None;
+        }
+
+        if distance > separation + ABSOLUTE_TOLERANCE {
+            let normal = vector::scale(vector::sub(point_b, point_a), 1.0 / distance);
+            return Some(Manifold {
+                normal,
+                points: vec![ContactPoint {
+                    point: midpoint_between_surfaces(a, point_a, b, point_b, normal),
+                    penetration: radii - distance,
+                    id: FeatureId::single(None),
+                }],
+            });
+        }
+    }
+
+    let normal = reference.normals[reference_edge];
+    let v1 = reference.vertices[reference_edge];
+    let v2 = reference.vertices[(reference_edge + 1) % reference.vertices.len()];
+
+    // Clip the incident edge to the side planes of the reference edge
+    let tangent = vector::normalize(vector::sub(v2, v1));
+    let incident_edge = incident_edge(incident, normal);
+    let i1 = incident.vertices[incident_edge];
+    let i2 = incident.vertices[(incident_edge + 1) % incident.vertices.len()];
+
+    let clipped = clip_segment(
+        (i1, i2),
+        vector::scale(tangent, -1.0),
+        -vector::dot(tangent, v1),
+    )?;
+    let clipped = clip_segment(clipped, tangent, vector::dot(tangent, v2))?;
+
+    // Keep the clipped points that are within reach of the reference face
+    let points: Vec<ContactPoint> = [clipped.0, clipped.1]
+        .into_iter()
+        .enumerate()
+        .filter_map(|(end, point)| {
+            let separation = vector::dot(normal, vector::sub(point, v1));
+            (separation < radii).then(|| {
+                let on_face = vector::sub(point, vector::scale(normal, separation));
+                ContactPoint {
+                    point: midpoint_between_surfaces(reference, on_face, incident, point, normal),
+                    penetration: radii - separation,
+                    id: FeatureId::clipped(reference_edge, incident_edge, end, flip),
+                }
+            })
+        })
+        .collect();
+
+    if points.is_empty() {
+        return None;
+    }
+
+    let manifold = Manifold { normal, points };
+    Some(if flip { manifold.flipped() } else { manifold })
+}
+
+/// Clip a segment to the half plane where `dot(normal, point) <= offset`
+///
+/// Returns `None` if the whole segment is outside the half plane.
+fn clip_segment(
+    segment: ((f32, f32), (f32, f32)),
+    normal: (f32, f32),
+    offset: f32,
+) -> Option<((f32, f32), (f32, f32))> {
+    let (p1, p2) = segment;
+    let d1 = vector::dot(normal, p1) - offset;
+    let d2 = vector::dot(normal, p2) - offset;
+
+    match (d1 <= 0.0, d2 <= 0.0) {
+        (true, true) => Some(segment),
+        (false, false) => None,
+        (inside_1, _) => {
+            let intersection = vector::add(p1, vector::scale(vector::sub(p2, p1), d1 / (d1 - d2)));
+            if inside_1 {
+                Some((p1, intersection))
+            } else {
+                Some((intersection, p2))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::material::Material;
+    use super::super::{BodyId, Circle, PhysicsEngine, Rectangle};
+    use super::*;
+
+    const BOUNCY: Material = Material {
+        restitution: 1.0,
+        static_friction: 0.0,
+        dynamic_friction: 0.0,
+        density: 1.0,
+    };
+
+    fn circle(radius: f32) -> Shape {
+        Shape::Circle(Circle {
+            origin: (0.0, 0.0),
+            radius,
+        })
+    }
+
+    fn at(position: (f32, f32)) -> Pose {
+        Pose {
+            position,
+            orientation: 0.0,
+        }
+    }
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32), tolerance: f32) {
+        assert!(
+            vector::length(vector::sub(actual, expected)) <= tolerance,
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    /// Add a frictionless, perfectly elastic unit circle
+    fn add_ball(engine: &mut PhysicsEngine, position: (f32, f32), velocity: (f32, f32)) -> BodyId {
+        let body = engine.add_object(Circle {
+            origin: (0.0, 0.0),
+            radius: 1.0,
+        });
+        body.set_material(BOUNCY);
+        body.set_position(position);
+        body.dynamics.velocity = velocity;
+        body.id
+    }
+
+    /// A step short enough that the bodies barely move before their contact is resolved
+    const INSTANT: Duration = Duration::from_micros(1);
+
+    #[test]
+    fn head_on_contact() {
+        let manifold = collide(
+            &circle(1.0),
+            &at((-0.9, 0.0)),
+            &circle(1.0),
+            &at((0.9, 0.0)),
+        )
+        .expect("overlapping circles should collide");
+        assert_close(manifold.normal, (1.0, 0.0), 1e-6);
+        assert_eq!(manifold.points.len(), 1);
+        assert!((manifold.points[0].penetration - 0.2).abs() < 1e-6);
+        assert_close(manifold.points[0].point, (0.0, 0.0), 1e-6);
+
+        // Equal masses swap velocities in a perfectly elastic head-on collision
+        let mut engine = PhysicsEngine::new();
+        let a = add_ball(&mut engine, (-0.9, 0.0), (1.0, 0.0));
+        let b = add_ball(&mut engine, (0.9, 0.0), (-1.0, 0.0));
+        engine.update(INSTANT);
+
+        let velocity = |id| engine.get_object(id).unwrap().dynamics.velocity;
+        assert_close(velocity(a), (-1.0, 0.0), 1e-4);
+        assert_close(velocity(b), (1.0, 0.0), 1e-4);
+    }
+
+    #[test]
+    fn glancing_contact() {
+        let offset = (0.6, 1.8);
+        let distance = vector::length(offset);
+        let manifold = collide(&circle(1.0), &at((0.0, 0.0)), &circle(1.0), &at(offset))
+            .expect("overlapping circles should collide");
+        assert_close(manifold.normal, vector::scale(offset, 1.0 / distance), 1e-6);
+        assert!((manifold.points[0].penetration - (2.0 - distance)).abs() < 1e-6);
+
+        // Without friction only the part of the velocity along the normal is passed on, and the rest carries on past
+        let mut engine = PhysicsEngine::new();
+        let a = add_ball(&mut engine, (0.0, 0.0), (2.0, 0.0));
+        let b = add_ball(&mut engine, offset, (0.0, 0.0));
+        engine.update(INSTANT);
+
+        let velocity = |id| engine.get_object(id).unwrap().dynamics.velocity;
+        assert_close(velocity(b), (0.2, 0.6), 1e-3);
+        assert_close(velocity(a), (1.8, -0.6), 1e-3);
+        assert!(
+            engine
+                .get_object(a)
+                .unwrap()
+                .dynamics
+                .angular_velocity
+                .abs()
+                < 1e-6
+        );
+    }
+
+    #[test]
+    fn separated_shapes_do_not_collide() {
+        assert!(collide(&circle(1.0), &at((0.0, 0.0)), &circle(1.0), &at((2.5, 0.0))).is_none());
+    }
+
+    #[test]
+    fn resting_contact_does_not_jitter() {
+        let mut engine = PhysicsEngine::new();
+        engine.gravity = (0.0, -9.81);
+
+        let ground = engine.add_object(Rectangle {
+            origin: (0.0, 0.0),
+            half_extents: (10.0, 0.5),
+        });
+        ground.dynamics.set_static();
+        ground.set_position((0.0, -0.5));
+
+        let ball = engine.add_object(Circle {
+            origin: (0.0, 0.0),
+            radius: 0.5,
+        });
+        ball.set_position((0.0, 0.5));
+        let ball = ball.id;
+
+        let dt = Duration::from_secs_f32(1.0 / 60.0);
+        for _ in 0..60 {
+            engine.update(dt);
+        }
+
+        let settled = engine.get_object(ball).unwrap().pose.position;
+        for _ in 0..1000 {
+            engine.update(dt);
+            let body = engine.get_object(ball).unwrap();
+            assert_close(body.pose.position, settled, 1e-3);
+            assert!(vector::length(body.dynamics.velocity) < 0.2);
+        }
+        assert!((settled.1 - 0.5).abs() < 0.02, "ball rests at {settled:?}");
+    }
+}
@@ -0,0 +1,273 @@
+//! Forces that act on bodies throughout the world
+//!
+//! Force fields are evaluated inside the integrator, so higher order integrators sample them at their intermediate states just like any other force. They never act on static bodies.
+
+use serde::{Deserialize, Serialize};
+
+use super::vector;
+
+/// A force that depends on where a body is and how it moves
+pub trait ForceField {
+    /// A short human-readable name
+    fn name(&self) -> &'static str;
+
+    /// The force on a body with the given position, velocity and mass
+    fn force(&self, position: (f32, f32), velocity: (f32, f32), mass: f32) -> (f32, f32);
+
+    /// The field as one of the built-in kinds, so it can be saved. Custom fields return `None`.
+    fn to_builtin(&self) -> Option<BuiltinField> {
+        None
+    }
+}
+
+/// The same acceleration everywhere, regardless of mass, like gravity near a planet's surface
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Uniform {
+    pub acceleration: (f32, f32),
+}
+
+impl ForceField for Uniform {
+    fn name(&self) -> &'static str {
+        "Uniform"
+    }
+
+    fn force(&self, _position: (f32, f32), _velocity: (f32, f32), mass: f32) -> (f32, f32) {
+        vector::scale(self.acceleration, mass)
+    }
+
+    fn to_builtin(&self) -> Option<BuiltinField> {
+        Some(BuiltinField::Uniform(*self))
+    }
+}
+
+/// Pulls bodies toward a point with an acceleration that falls off with the square of the distance, like the gravity of a star
+///
+/// A negative strength pushes bodies away instead.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct PointAttractor {
+    pub center: (f32, f32),
+    /// The acceleration at unit distance, which is the gravitational parameter GM for gravity
+    pub strength: f32,
+    /// Distances are clamped to at least this much so the acceleration stays finite near the center
+    pub min_distance: f32,
+}
+
+impl ForceField for PointAttractor {
+    fn name(&self) -> &'static str {
+        "Point attractor"
+    }
+
+    fn force(&self, position: (f32, f32), _velocity: (f32, f32), mass: f32) -> (f32, f32) {
+        let offset = vector::sub(self.center, position);
+        let distance = vector::length(offset).max(self.min_distance);
+        if distance <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        // The offset over the distance cubed is the unit direction over the distance squared
+        vector::scale(
+            offset,
+            mass * self.strength / (distance * distance * distance),
+        )
+    }
+
+    fn to_builtin(&self) -> Option<BuiltinField> {
+        Some(BuiltinField::PointAttractor(*self))
+    }
+}
+
+/// Resists motion with a force proportional to velocity, like moving slowly through a thick fluid
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct LinearDrag {
+    /// Force per unit of speed
+    pub coefficient: f32,
+}
+
+impl ForceField for LinearDrag {
+    fn name(&self) -> &'static str {
+        "Linear drag"
+    }
+
+    fn force(&self, _position: (f32, f32), velocity: (f32, f32), _mass: f32) -> (f32, f32) {
+        vector::scale(velocity, -self.coefficient)
+    }
+
+    fn to_builtin(&self) -> Option<BuiltinField> {
+        Some(BuiltinField::LinearDrag(*self))
+    }
+}
+
+/// Swirls bodies counter-clockwise around a point with an acceleration that falls off with distance
+///
+/// A negative strength swirls clockwise.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Vortex {
+    pub center: (f32, f32),
+    /// The tangential acceleration at unit distance
+    pub strength: f32,
+    /// Distances are clamped to at least this much so the acceleration stays finite near the center
+    pub min_distance: f32,
+}
+
+impl ForceField for Vortex {
+    fn name(&self) -> &'static str {
+        "Vortex"
+    }
+
+    fn force(&self, position: (f32, f32), _velocity: (f32, f32), mass: f32) -> (f32, f32) {
+        let offset = vector::sub(position, self.center);
+        let distance = vector::length(offset).max(self.min_distance);
+        if distance <= 0.0 {
+            return (0.0, 0.0);
+        }
+
+        // Perpendicular to the offset, with magnitude strength / distance
+        let tangent = vector::cross_scalar(1.0, offset);
+        vector::scale(tangent, mass * self.strength / (distance * distance))
+    }
+
+    fn to_builtin(&self) -> Option<BuiltinField> {
+        Some(BuiltinField::Vortex(*self))
+    }
+}
+
+/// Any of the force fields that come with the engine
+///
+/// Built-in fields can be stored in scene files.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum BuiltinField {
+    Uniform(Uniform),
+    PointAttractor(PointAttractor),
+    LinearDrag(LinearDrag),
+    Vortex(Vortex),
+}
+
+impl BuiltinField {
+    fn as_field(&self) -> &dyn ForceField {
+        match self {
+            BuiltinField::Uniform(field) => field,
+            BuiltinField::PointAttractor(field) => field,
+            BuiltinField::LinearDrag(field) => field,
+            BuiltinField::Vortex(field) => field,
+        }
+    }
+}
+
+impl ForceField for BuiltinField {
+    fn name(&self) -> &'static str {
+        self.as_field().name()
+    }
+
+    fn force(&self, position: (f32, f32), velocity: (f32, f32), mass: f32) -> (f32, f32) {
+        self.as_field().force(position, velocity, mass)
+    }
+
+    fn to_builtin(&self) -> Option<BuiltinField> {
+        Some(*self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            vector::length(vector::sub(actual, expected)) < 1e-5,
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn uniform_field_gives_every_body_the_same_acceleration() {
+        let field = Uniform {
+            acceleration: (0.0, -9.81),
+        };
+        assert_close(field.force((3.0, 4.0), (1.0, 0.0), 2.0), (0.0, -19.62));
+        assert_close(field.force((-50.0, 0.0), (0.0, 0.0), 0.5), (0.0, -4.905));
+    }
+
+    #[test]
+    fn point_attractor_falls_off_with_distance_squared() {
+        let field = PointAttractor {
+            center: (1.0, 1.0),
+            strength: 8.0,
+            min_distance: 0.5,
+        };
+
+        assert_close(field.force((3.0, 1.0), (0.0, 0.0), 1.0), (-2.0, 0.0));
+        assert_close(field.force((1.0, 5.0), (0.0, 0.0), 1.0), (0.0, -0.5));
+        assert_close(field.force((1.0, 5.0), (0.0, 0.0), 3.0), (0.0, -1.5));
+
+        let repeller = PointAttractor {
+            strength: -8.0,
+            ..field
+        };
+        assert_close(repeller.force((3.0, 1.0), (0.0, 0.0), 1.0), (2.0, 0.0));
+    }
+
+    #[test]
+    fn point_attractor_is_capped_inside_its_minimum_distance() {
+        let field = PointAttractor {
+            center: (0.0, 0.0),
+            strength: 1.0,
+            min_distance: 0.5,
+        };
+
+        // Inside the minimum distance the pull grows linearly toward zero at the center instead of blowing up
+        assert_close(field.force((0.5, 0.0), (0.0, 0.0), 1.0), (-4.0, 0.0));
+        assert_close(field.force((0.25, 0.0), (0.0, 0.0), 1.0), (-2.0, 0.0));
+        assert_close(field.force((0.0, 0.0), (0.0, 0.0), 1.0), (0.0, 0.0));
+
+        let unclamped = PointAttractor {
+            min_distance: 0.0,
+            ..field
+        };
+        assert_close(unclamped.force((0.0, 0.0), (0.0, 0.0), 1.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn linear_drag_opposes_velocity() {
+        let field = LinearDrag { coefficient: 0.5 };
+        assert_close(field.force((9.0, 9.0), (4.0, -2.0), 10.0), (-2.0, 1.0));
+        assert_close(field.force((9.0, 9.0), (0.0, 0.0), 10.0), (0.0, 0.0));
+    }
+
+    #[test]
+    fn vortex_swirls_counter_clockwise_and_falls_off_with_distance() {
+        let field = Vortex {
+            center: (0.0, 0.0),
+            strength: 4.0,
+            min_distance: 0.5,
+        };
+
+        assert_close(field.force((2.0, 0.0), (0.0, 0.0), 1.0), (0.0, 2.0));
+        assert_close(field.force((0.0, 4.0), (0.0, 0.0), 1.0), (-1.0, 0.0));
+        assert_close(field.force((0.0, 0.0), (0.0, 0.0), 1.0), (0.0, 0.0));
+
+        let clockwise = Vortex {
+            strength: -4.0,
+            ..field
+        };
+        assert_close(clockwise.force((2.0, 0.0), (0.0, 0.0), 1.0), (0.0, -2.0));
+    }
+
+    #[test]
+    fn builtin_fields_round_trip_through_json() {
+        let field = BuiltinField::Vortex(Vortex {
+            center: (1.0, 2.0),
+            strength: 3.0,
+            min_distance: 0.25,
+        });
+        let json = serde_json::to_string(&field).unwrap();
+        assert!(json.contains(r#""type":"vortex""#), "{json}");
+
+        let restored: BuiltinField = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.name(), "Vortex");
+        assert_close(
+            restored.force((3.0, 2.0), (0.0, 0.0), 1.0),
+            field.force((3.0, 2.0), (0.0, 0.0), 1.0),
+        );
+    }
+}
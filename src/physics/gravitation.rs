@@ -0,0 +1,606 @@
+//! Mutual gravitational attraction between bodies
+//!
+//! Every non-static body pulls on every other one with a force proportional to both masses over the square of the distance between them. Bodies are treated as point masses at their positions, which is exact for circles as long as they do not overlap.
+
+use serde::{Deserialize, Serialize};
+
+use super::vector;
+
+/// How the attraction between every pair of bodies is summed
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Approximation {
+    /// Sum over every pair exactly. Takes time proportional to the square of the number of bodies.
+    Direct,
+
+    /// Group distant bodies into the cells of a quadtree and attract toward each cell's center of mass
+    ///
+    /// Takes time proportional to n log n. A cell is used as a whole when its size over its distance is below `theta`, so 0.0 is exact and larger values are faster but rougher. 0.5 is a common choice.
+    BarnesHut { theta: f32 },
+}
+
+/// Gravitational attraction between all non-static bodies
+///
+/// Static bodies have infinite mass, so they neither attract nor are attracted. Use a point attractor force field for a fixed center of attraction.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct NBodyGravity {
+    /// The gravitational constant G
+    pub constant: f32,
+
+    /// Added to every distance in quadrature so close encounters do not produce huge accelerations. Zero gives exact inverse-square attraction.
+    #[serde(default)]
+    pub softening: f32,
+
+    pub approximation: Approximation,
+}
+
+impl NBodyGravity {
+    /// Exact attraction with the given gravitational constant and no softening
+    pub fn new(constant: f32) -> NBodyGravity {
+        NBodyGravity {
+            constant,
+            softening: 0.0,
+            approximation: Approximation::Direct,
+        }
+    }
+
+    /// The acceleration of every body due to all of the others
+    ///
+    /// Bodies with a mass of zero feel the attraction of the others but do not attract anything themselves.
+    pub fn accelerations(
+        &self,
+        positions: &[(f32, f32)],
+        masses: &[f32],
+        accelerations: &mut [(f32, f32)],
+    ) {
+        accelerations.fill((0.0, 0.0));
+        match self.approximation {
+            Approximation::Direct => self.direct_accelerations(positions, masses, accelerations),
+            Approximation::BarnesHut { theta } => {
+                let tree = QuadTree::new(positions, masses);
+                for (i, acceleration) in accelerations.iter_mut().enumerate() {
+                    *acceleration = self.tree_acceleration(&tree, i, positions, masses, theta);
+                }
+            }
+        }
+    }
+
+    /// The total gravitational potential energy of a set of bodies, always summed exactly
+    pub fn potential_energy(&self, positions: &[(f32, f32)], masses: &[f32]) -> f32 {
+        let mut energy = 0.0;
+        for i in 0..positions.len() {
+            for j in (i + 1)..positions.len() {
+                let distance_squared =
+                    vector::length_squared(vector::sub(positions[j], positions[i]))
+                        + self.softening * self.softening;
+                if distance_squared > 0.0 {
+                    energy -= self.constant * masses[i] * masses[j] / distance_squared.sqrt();
+                }
+            }
+        }
+        energy
+    }
+
+    /// The acceleration toward a mass at `source` of a body at `position`, divided by the mass
+    fn pull(&self, position: (f32, f32), source: (f32, f32)) -> (f32, f32) {
+        let offset = vector::sub(source, position);
+        let distance_squared = vector::length_squared(offset) + self.softening * self.softening;
+        if distance_squared <= 0.0 {
+            return (0.0, 0.0);
+        }
+        vector::scale(
+            offset,
+            self.constant / (distance_squared * distance_squared.sqrt()),
+        )
+    }
+
+    fn direct_accelerations(
+        &self,
+        positions: &[(f32, f32)],
+        masses: &[f32],
+        accelerations: &mut [(f32, f32)],
+    ) {
+        // Each pair is visited once and pulls both bodies
+        for i in 0..positions.len() {
+            for j in (i + 1)..positions.len() {
+                let pull = self.pull(positions[i], positions[j]);
+                accelerations[i] = vector::add(accelerations[i], vector::scale(pull, masses[j]));
+                accelerations[j] = vector::sub(accelerations[j], vector::scale(pull, masses[i]));
+            }
+        }
+    }
+
+    fn tree_acceleration(
+        &self,
+        tree: &QuadTree,
+        body: usize,
+        positions: &[(f32, f32)],
+        masses: &[f32],
+        theta: f32,
+    ) -> (f32, f32) {
+        let position = positions[body];
+        let mut acceleration = (0.0, 0.0);
+
+        let mut stack = vec![0];
+        while let Some(index) = stack.pop() {
+            let Some(node) = tree.nodes.get(index) else {
+                continue;
+            };
+            if node.mass <= 0.0 {
+                continue;
+            }
+
+            let Some(first_child) = node.first_child else {
+                for &other in &node.bodies {
+                    if other != body {
+                        let pull = self.pull(position, positions[other]);
+                        acceleration =
+                            vector::add(acceleration, vector::scale(pull, masses[other]));
+                    }
+                }
+                continue;
+            };
+
+            // A cell containing the body is always opened so the body never attracts itself
+            let center_of_mass = node.center_of_mass();
+            let distance = vector::length(vector::sub(center_of_mass, position));
+            if !node.contains(position) && 2.0 * node.half_size < theta * distance {
+                let pull = self.pull(position, center_of_mass);
+                acceleration = vector::add(acceleration, vector::scale(pull, node.mass));
+            } else {
+                stack.extend(first_child..(first_child + 4));
+            }
+        }
+
+        acceleration
+    }
+}
+
+/// Cells deeper than this hold all of their bodies in one leaf, so bodies at the same position cannot subdivide forever
+const MAX_TREE_DEPTH: usize = 24;
+
+/// One square cell of a quadtree
+struct Node {
+    center: (f32, f32),
+    half_size: f32,
+
+    /// Total mass of every body in the cell
+    mass: f32,
+    /// Sum of mass times position, which gives the center of mass once divided by the mass
+    weighted_position: (f32, f32),
+
+    /// The four children are stored next to each other, starting here
+    first_child: Option<usize>,
+    /// The bodies in a leaf. Empty for cells with children.
+    bodies: Vec<usize>,
+}
+
+impl Node {
+    fn new(center: (f32, f32), half_size: f32) -> Node {
+        Node {
+            center,
+            half_size,
+            mass: 0.0,
+            weighted_position: (0.0, 0.0),
+            first_child: None,
+            bodies: Vec::new(),
+        }
+    }
+
+    fn center_of_mass(&self) -> (f32, f32) {
+        vector::scale(self.weighted_position, 1.0 / self.mass)
+    }
+
+    fn contains(&self, point: (f32, f32)) -> bool {
+        (point.0 - self.center.0).abs() <= self.half_size
+            && (point.1 - self.center.1).abs() <= self.half_size
+    }
+
+    /// Which of the four children a point belongs in
+    fn quadrant(&self, point: (f32, f32)) -> usize {
+        (point.0 >= self.center.0) as usize | ((point.1 >= self.center.1) as usize) << 1
+    }
+}
+
+/// A quadtree over the bodies with mass, used for the Barnes–Hut approximation
+struct QuadTree {
+    nodes: Vec<Node>,
+}
+
+impl QuadTree {
+    fn new(positions: &[(f32, f32)], masses: &[f32]) -> QuadTree {
+        let mut min = (f32::INFINITY, f32::INFINITY);
+        let mut max = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        for (&position, _) in positions.iter().zip(masses).filter(|(_, &mass)| mass > 0.0) {
+            min = (min.0.min(position.0), min.1.min(position.1));
+            max = (max.0.max(position.0), max.1.max(position.1));
+        }
+
+        let mut tree = QuadTree { nodes: Vec::new() };
+        if min.0 > max.0 {
+            return tree;
+        }
+
+        let center = vector::scale(vector::add(min, max), 0.5);
+        let half_size = 0.5 * (max.0 - min.0).max(max.1 - min.1).max(f32::EPSILON);
+        tree.nodes.push(Node::new(center, half_size));
+
+        for (body, &mass) in masses.iter().enumerate() {
+            if mass > 0.0 {
+                tree.insert(0, body, 0, positions, masses);
+            }
+        }
+
+        tree
+    }
+
+    fn insert(
+        &mut self,
+        index: usize,
+        body: usize,
+        depth: usize,
+        positions: &[(f32, f32)],
+        masses: &[f32],
+    ) {
+        let position = positions[body];
+        let node = &mut self.nodes[index];
+        node.mass += masses[body];
+        node.weighted_position = vector::add(
+            node.weighted_position,
+            vector::scale(position, masses[body]),
+        );
+
+        if let Some(first_child) = node.first_child {
+            let child = first_child + node.quadrant(position);
+            self.insert(child, body, depth + 1, positions, masses);
+            return;
+        }
+
+        if node.bodies.is_empty() || depth >= MAX_TREE_DEPTH {
+            node.bodies.push(body);
+            return;
+        }
+
+        // Split the leaf and move its body down alongside the new one
+        let center = node.center;
+        let quarter = 0.5 * node.half_size;
+        let first_child = self.nodes.len();
+        let existing = std::mem::take(&mut self.nodes[index].bodies);
+        self.nodes[index].first_child = Some(first_child);
+        for quadrant in 0..4 {
+            let offset = (
+                if quadrant & 1 == 0 { -quarter } else { quarter },
+                if quadrant & 2 == 0 { -quarter } else { quarter },
+            );
+            self.nodes
+                .push(Node::new(vector::add(center, offset), quarter));
+        }
+
+        for other in existing {
+            let child = first_child + self.nodes[index].quadrant(positions[other]);
+            self.insert(child, other, depth + 1, positions, masses);
+        }
+        let child = first_child + self.nodes[index].quadrant(position);
+        self.insert(child, body, depth + 1, positions, masses);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::TAU;
+    use std::time::Duration;
+
+    use super::super::integrator::{
+        ExplicitEuler, Integrator, RungeKutta4, SemiImplicitEuler, VelocityVerlet,
+    };
+    use super::super::random::Xorshift;
+    use super::super::{Circle, PhysicsEngine};
+    use super::*;
+
+    /// How closely a simulated two-body orbit matched the exact solution
+    struct OrbitCheck {
+        /// Relative difference between the measured and exact orbital period
+        ///
+        /// NaN if the orbits never completed, which happens when an integrator adds so much energy that the bodies drift apart.
+        period_error: f32,
+        /// Largest relative change in total energy seen at any step
+        max_energy_drift: f32,
+    }
+
+    /// Simulate two equal circles in a circular orbit around each other and compare against the exact solution
+    ///
+    /// The circles have unit gravitational constant and mass 50, ten units apart, so the exact period is 2π√10 seconds. The run goes through the whole physics engine, so it checks the force model and its plumbing together.
+    fn two_body_orbit(
+        approximation: Approximation,
+        integrator: Box<dyn Integrator>,
+        steps_per_orbit: u32,
+        orbits: u32,
+    ) -> OrbitCheck {
+        let mass = 50.0;
+        let separation = 10.0;
+        let gravity = NBodyGravity {
+            approximation,
+            ..NBodyGravity::new(1.0)
+        };
+
+        // Each circle moves at half the relative orbital speed around the shared center of mass
+        let relative_speed = (gravity.constant * 2.0 * mass / separation).sqrt();
+        let period = TAU * separation / relative_speed;
+
+        let mut engine = PhysicsEngine::new();
+        engine.set_integrator(integrator);
+        engine.n_body_gravity = Some(gravity);
+
+        let mut ids = Vec::new();
+        for side in [-1.0, 1.0] {
+            let body = engine.add_object(Circle {
+                origin: (0.0, 0.0),
+                radius: 0.5,
+            });
+            body.set_position((side * 0.5 * separation, 0.0));
+            body.dynamics.set_mass(mass);
+            body.dynamics.velocity = (0.0, side * 0.5 * relative_speed);
+            ids.push(body.id);
+        }
+
+        let state = |engine: &PhysicsEngine| {
+            let bodies = [
+                engine.get_object(ids[0]).unwrap(),
+                engine.get_object(ids[1]).unwrap(),
+            ];
+            let positions = bodies.map(|body| body.pose.position);
+            let kinetic: f32 = bodies
+                .iter()
+                .map(|body| 0.5 * mass * vector::length_squared(body.dynamics.velocity))
+                .sum();
+            let energy = kinetic + gravity.potential_energy(&positions, &[mass, mass]);
+            let offset = vector::sub(positions[1], positions[0]);
+            (energy, offset.1.atan2(offset.0))
+        };
+
+        let dt = Duration::from_secs_f32(period / steps_per_orbit as f32);
+        let (initial_energy, mut previous_angle) = state(&engine);
+        let mut max_energy_drift: f32 = 0.0;
+        let mut swept_angle = 0.0;
+        let mut time = 0.0;
+        let mut measured_period = f32::NAN;
+
+        for _ in 0..(steps_per_orbit * (orbits + 1)) {
+            engine.update(dt);
+            time += dt.as_secs_f32();
+
+            let (energy, angle) = state(&engine);
+            max_energy_drift =
+                max_energy_drift.max(((energy - initial_energy) / initial_energy).abs());
+
+            // Unwrap the angle so it keeps growing past a full turn
+            let mut step_angle = angle - previous_angle;
+            if step_angle > TAU / 2.0 {
+                step_angle -= TAU;
+            } else if step_angle < -TAU / 2.0 {
+                step_angle += TAU;
+            }
+            previous_angle = angle;
+
+            // Interpolate the moment the last full turn completed
+            let target = TAU * orbits as f32;
+            if measured_period.is_nan() && swept_angle + step_angle >= target && step_angle > 0.0 {
+                let fraction = (target - swept_angle) / step_angle;
+                measured_period = (time - dt.as_secs_f32() * (1.0 - fraction)) / orbits as f32;
+            }
+            swept_angle += step_angle;
+        }
+
+        OrbitCheck {
+            period_error: (measured_period - period) / period,
+            max_energy_drift,
+        }
+    }
+
+    const PERIOD_TOLERANCE: f32 = 1e-3;
+    const ENERGY_TOLERANCE: f32 = 1e-3;
+
+    /// Ten orbits of 500 steps each
+    fn orbit(approximation: Approximation, integrator: Box<dyn Integrator>) -> OrbitCheck {
+        two_body_orbit(approximation, integrator, 500, 10)
+    }
+
+    #[test]
+    fn accurate_integrators_keep_the_exact_orbit() {
+        let integrators: [Box<dyn Integrator>; 3] = [
+            Box::new(SemiImplicitEuler),
+            Box::new(VelocityVerlet),
+            Box::new(RungeKutta4),
+        ];
+        for integrator in integrators {
+            let name = integrator.name();
+            let check = orbit(Approximation::Direct, integrator);
+            assert!(
+                check.period_error.abs() < PERIOD_TOLERANCE,
+                "{name} period error {}",
+                check.period_error
+            );
+            assert!(
+                check.max_energy_drift < ENERGY_TOLERANCE,
+                "{name} energy drift {}",
+                check.max_energy_drift
+            );
+        }
+    }
+
+    #[test]
+    fn explicit_euler_orbit_gains_energy() {
+        let check = orbit(Approximation::Direct, Box::new(ExplicitEuler));
+        assert!(
+            check.max_energy_drift > 0.1,
+            "energy drift {}",
+            check.max_energy_drift
+        );
+    }
+
+    /// Two binaries of unit masses one unit apart, orbiting each other twenty units apart
+    ///
+    /// Seen from either binary, the other is far smaller than its distance, so Barnes-Hut with a theta of 0.5 treats it as one body. Returns the positions and velocities.
+    fn double_binary() -> Vec<((f32, f32), (f32, f32))> {
+        let inner_speed = 0.5 * 2.0_f32.sqrt();
+        let outer_speed = 0.5 * (4.0_f32 / 20.0).sqrt();
+
+        [-1.0, 1.0]
+            .into_iter()
+            .flat_map(|binary| {
+                [-1.0, 1.0].map(|side| {
+                    (
+                        (binary * 10.0 + side * 0.5, 0.0),
+                        (0.0, binary * outer_speed + side * inner_speed),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    /// Run the double binary through the engine for twenty seconds, about four turns of each binary, returning the final positions and the largest relative change in total energy
+    fn simulate_double_binary(approximation: Approximation) -> (Vec<(f32, f32)>, f32) {
+        let gravity = NBodyGravity {
+            approximation,
+            ..NBodyGravity::new(1.0)
+        };
+        let mut engine = PhysicsEngine::new();
+        engine.set_integrator(Box::new(VelocityVerlet));
+        engine.n_body_gravity = Some(gravity);
+
+        let ids: Vec<_> = double_binary()
+            .into_iter()
+            .map(|(position, velocity)| {
+                let body = engine.add_object(Circle {
+                    origin: (0.0, 0.0),
+                    radius: 0.1,
+                });
+                body.set_position(position);
+                body.dynamics.set_mass(1.0);
+                body.dynamics.velocity = velocity;
+                body.id
+            })
+            .collect();
+
+        let masses = vec![1.0; ids.len()];
+        let state = |engine: &PhysicsEngine| {
+            let bodies: Vec<_> = ids
+                .iter()
+                .map(|&id| engine.get_object(id).unwrap())
+                .collect();
+            let positions: Vec<_> = bodies.iter().map(|body| body.pose.position).collect();
+            let kinetic: f32 = bodies
+                .iter()
+                .map(|body| 0.5 * vector::length_squared(body.dynamics.velocity))
+                .sum();
+            let energy = kinetic + gravity.potential_energy(&positions, &masses);
+            (positions, energy)
+        };
+
+        let (_, initial_energy) = state(&engine);
+        let mut max_energy_drift: f32 = 0.0;
+        for _ in 0..2000 {
+            engine.update(Duration::from_secs_f32(0.01));
+            let (_, energy) = state(&engine);
+            max_energy_drift =
+                max_energy_drift.max(((energy - initial_energy) / initial_energy).abs());
+        }
+
+        (state(&engine).0, max_energy_drift)
+    }
+
+    #[test]
+    fn barnes_hut_double_binary_matches_direct() {
+        // The distant binary really is grouped, so the accelerations are not exact
+        let (positions, _): (Vec<_>, Vec<_>) = double_binary().into_iter().unzip();
+        let masses = vec![1.0; positions.len()];
+        let accelerations = |approximation| {
+            let mut accelerations = vec![(0.0, 0.0); positions.len()];
+            NBodyGravity {
+                approximation,
+                ..NBodyGravity::new(1.0)
+            }
+            .accelerations(&positions, &masses, &mut accelerations);
+            accelerations
+        };
+        let barnes_hut = Approximation::BarnesHut { theta: 0.5 };
+        assert!(accelerations(barnes_hut) != accelerations(Approximation::Direct));
+
+        let (direct, _) = simulate_double_binary(Approximation::Direct);
+        let (approximate, energy_drift) = simulate_double_binary(barnes_hut);
+        for (approximate, direct) in approximate.into_iter().zip(direct) {
+            let error = vector::length(vector::sub(approximate, direct));
+            assert!(error < 5e-3, "ended {error} from the direct sum");
+        }
+        assert!(
+            energy_drift < ENERGY_TOLERANCE,
+            "energy drift {energy_drift}"
+        );
+    }
+
+    /// Bodies scattered in clumps of different density, with masses from 1 to 10
+    fn scattered_bodies(count: usize) -> (Vec<(f32, f32)>, Vec<f32>) {
+        let mut random = Xorshift::new(0x1234_5678);
+
+        let clumps = [
+            ((0.0, 0.0), 40.0),
+            ((60.0, 20.0), 10.0),
+            ((-30.0, 50.0), 5.0),
+        ];
+        (0..count)
+            .map(|i| {
+                let (center, spread) = clumps[i % clumps.len()];
+                let position = (
+                    center.0 + (random.next_f32() - 0.5) * spread,
+                    center.1 + (random.next_f32() - 0.5) * spread,
+                );
+                (position, 1.0 + 9.0 * random.next_f32())
+            })
+            .unzip()
+    }
+
+    /// The relative error of each Barnes-Hut acceleration against the direct sum, sorted
+    fn barnes_hut_errors(theta: f32) -> Vec<f32> {
+        let (positions, masses) = scattered_bodies(600);
+        let gravity = |approximation| NBodyGravity {
+            softening: 0.1,
+            approximation,
+            ..NBodyGravity::new(1.0)
+        };
+
+        let mut exact = vec![(0.0, 0.0); positions.len()];
+        gravity(Approximation::Direct).accelerations(&positions, &masses, &mut exact);
+        let mut approximate = vec![(0.0, 0.0); positions.len()];
+        gravity(Approximation::BarnesHut { theta }).accelerations(
+            &positions,
+            &masses,
+            &mut approximate,
+        );
+
+        let mut errors: Vec<f32> = exact
+            .iter()
+            .zip(&approximate)
+            .map(|(&exact, &approximate)| {
+                vector::length(vector::sub(approximate, exact)) / vector::length(exact)
+            })
+            .collect();
+        errors.sort_by(f32::total_cmp);
+        errors
+    }
+
+    #[test]
+    fn barnes_hut_approximates_direct_accelerations() {
+        let errors = barnes_hut_errors(0.5);
+        let median = errors[errors.len() / 2];
+        let worst = errors[errors.len() - 1];
+        assert!(median > 0.0, "the tree was never used as an approximation");
+        assert!(median < 0.02, "median error {median}");
+        assert!(worst < 0.2, "worst error {worst}");
+    }
+
+    #[test]
+    fn barnes_hut_with_zero_theta_is_exact() {
+        let worst = *barnes_hut_errors(0.0).last().unwrap();
+        assert!(worst < 1e-4, "worst error {worst}");
+    }
+}
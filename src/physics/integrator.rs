@@ -0,0 +1,330 @@
+//! Numerical integration schemes for advancing body states through time
+
+use serde::{Deserialize, Serialize};
+
+use super::vector;
+
+/// The part of a body's state that an integrator advances
+#[derive(Clone, Copy, Default)]
+pub struct State {
+    pub position: (f32, f32),
+    pub velocity: (f32, f32),
+    pub orientation: f32,
+    pub angular_velocity: f32,
+}
+
+/// Linear and angular acceleration of one body
+#[derive(Clone, Copy, Default)]
+pub struct Acceleration {
+    pub linear: (f32, f32),
+    pub angular: f32,
+}
+
+/// Evaluates the acceleration of every state in a set, writing into the matching slot of the output
+///
+/// Accelerations may depend on the positions and velocities of any of the states, so higher order integrators call this several times per step with trial states.
+pub type AccelerationFn<'a> = dyn Fn(&[State], &mut [Acceleration]) + 'a;
+
+/// A numerical integration scheme
+///
+/// Integrators advance a set of states by one time step given a function for their accelerations.
+pub trait Integrator {
+    /// A short human-readable name
+    fn name(&self) -> &'static str;
+
+    /// Advance all states by `dt` seconds
+    fn integrate(&self, states: &mut [State], dt: f32, acceleration: &AccelerationFn);
+
+    /// The integrator as one of the built-in kinds, so it can be saved. Custom integrators return `None`.
+    fn to_builtin(&self) -> Option<BuiltinIntegrator> {
+        None
+    }
+}
+
+/// Explicit (forward) Euler: positions advance with the velocity from the start of the step
+///
+/// First order and gains energy in oscillating systems. Mostly useful as a baseline.
+pub struct ExplicitEuler;
+
+impl Integrator for ExplicitEuler {
+    fn name(&self) -> &'static str {
+        "Explicit Euler"
+    }
+
+    fn to_builtin(&self) -> Option<BuiltinIntegrator> {
+        Some(BuiltinIntegrator::ExplicitEuler)
+    }
+
+    fn integrate(&self, states: &mut [State], dt: f32, acceleration: &AccelerationFn) {
+        let accelerations = evaluate(states, acceleration);
+
+        for (state, a) in states.iter_mut().zip(accelerations.iter()) {
+            state.position = vector::add(state.position, vector::scale(state.velocity, dt));
+            state.orientation += state.angular_velocity * dt;
+
+            state.velocity = vector::add(state.velocity, vector::scale(a.linear, dt));
+            state.angular_velocity += a.angular * dt;
+        }
+    }
+}
+
+/// Semi-implicit (symplectic) Euler: velocities advance first and the new velocity moves the position
+///
+/// First order, but energy stays bounded in oscillating systems.
+pub struct SemiImplicitEuler;
+
+impl Integrator for SemiImplicitEuler {
+    fn name(&self) -> &'static str {
+        "Semi-implicit Euler"
+    }
+
+    fn to_builtin(&self) -> Option<BuiltinIntegrator> {
+        Some(BuiltinIntegrator::SemiImplicitEuler)
+    }
+
+    fn integrate(&self, states: &mut [State], dt: f32, acceleration: &AccelerationFn) {
+        let accelerations = evaluate(states, acceleration);
+
+        for (state, a) in states.iter_mut().zip(accelerations.iter()) {
+            state.velocity = vector::add(state.velocity, vector::scale(a.linear, dt));
+            state.angular_velocity += a.angular * dt;
+
+            state.position = vector::add(state.position, vector::scale(state.velocity, dt));
+            state.orientation += state.angular_velocity * dt;
+        }
+    }
+}
+
+/// Velocity Verlet: second order and symplectic, at the cost of two acceleration evaluations per step
+///
+/// Velocity-dependent accelerations are evaluated with the half-step velocity.
+pub struct VelocityVerlet;
+
+impl Integrator for VelocityVerlet {
+    fn name(&self) -> &'static str {
+        "Velocity Verlet"
+    }
+
+    fn to_builtin(&self) -> Option<BuiltinIntegrator> {
+        Some(BuiltinIntegrator::VelocityVerlet)
+    }
+
+    fn integrate(&self, states: &mut [State], dt: f32, acceleration: &AccelerationFn) {
+        let accelerations = evaluate(states, acceleration);
+
+        // Half kick, then drift with the half-step velocity
+        for (state, a) in states.iter_mut().zip(accelerations.iter()) {
+            state.velocity = vector::add(state.velocity, vector::scale(a.linear, dt / 2.0));
+            state.angular_velocity += a.angular * dt / 2.0;
+
+            state.position = vector::add(state.position, vector::scale(state.velocity, dt));
+            state.orientation += state.angular_velocity * dt;
+        }
+
+        // Second half kick with the acceleration at the new position
+        let accelerations = evaluate(states, acceleration);
+
+        for (state, a) in states.iter_mut().zip(accelerations.iter()) {
+            state.velocity = vector::add(state.velocity, vector::scale(a.linear, dt / 2.0));
+            state.angular_velocity += a.angular * dt / 2.0;
+        }
+    }
+}
+
+/// Classic fourth order Runge-Kutta
+///
+/// Very accurate over a single step with four acceleration evaluations, but not symplectic, so energy slowly drifts over long runs.
+pub struct RungeKutta4;
+
+impl Integrator for RungeKutta4 {
+    fn name(&self) -> &'static str {
+        "Runge-Kutta 4"
+    }
+
+    fn to_builtin(&self) -> Option<BuiltinIntegrator> {
+        Some(BuiltinIntegrator::RungeKutta4)
+    }
+
+    fn integrate(&self, states: &mut [State], dt: f32, acceleration: &AccelerationFn) {
+        let initial = states.to_vec();
+
+        let k1 = derivative(&initial, acceleration);
+        let k2 = derivative(&offset(&initial, &k1, dt / 2.0), acceleration);
+        let k3 = derivative(&offset(&initial, &k2, dt / 2.0), acceleration);
+        let k4 = derivative(&offset(&initial, &k3, dt), acceleration);
+
+        for (i, state) in states.iter_mut().enumerate() {
+            let weighted = |f: fn(&State) -> (f32, f32)| {
+                let sum = vector::add(
+                    vector::add(f(&k1[i]), vector::scale(f(&k2[i]), 2.0)),
+                    vector::add(vector::scale(f(&k3[i]), 2.0), f(&k4[i])),
+                );
+                vector::scale(sum, dt / 6.0)
+            };
+
+            // Each derivative stores the rate of change of a state in the matching field
+            state.position = vector::add(state.position, weighted(|d| d.position));
+            state.velocity = vector::add(state.velocity, weighted(|d| d.velocity));
+
+            let angular = weighted(|d| (d.orientation, d.angular_velocity));
+            state.orientation += angular.0;
+            state.angular_velocity += angular.1;
+        }
+    }
+}
+
+/// Any of the integrators that come with the engine
+///
+/// Built-in integrators can be stored in recordings.
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinIntegrator {
+    ExplicitEuler,
+    SemiImplicitEuler,
+    VelocityVerlet,
+    RungeKutta4,
+}
+
+impl BuiltinIntegrator {
+    fn as_integrator(&self) -> &dyn Integrator {
+        match self {
+            BuiltinIntegrator::ExplicitEuler => &ExplicitEuler,
+            BuiltinIntegrator::SemiImplicitEuler => &SemiImplicitEuler,
+            BuiltinIntegrator::VelocityVerlet => &VelocityVerlet,
+            BuiltinIntegrator::RungeKutta4 => &RungeKutta4,
+        }
+    }
+}
+
+impl Integrator for BuiltinIntegrator {
+    fn name(&self) -> &'static str {
+        self.as_integrator().name()
+    }
+
+    fn integrate(&self, states: &mut [State], dt: f32, acceleration: &AccelerationFn) {
+        self.as_integrator().integrate(states, dt, acceleration)
+    }
+
+    fn to_builtin(&self) -> Option<BuiltinIntegrator> {
+        Some(*self)
+    }
+}
+/// Evaluate the acceleration function into a new buffer
+fn evaluate(states: &[State], acceleration: &AccelerationFn) -> Vec<Acceleration> {
+    let mut accelerations = vec![Acceleration::default(); states.len()];
+    acceleration(states, &mut accelerations);
+    accelerations
+}
+
+/// The time derivative of each state, stored in a `State` for convenience
+fn derivative(states: &[State], acceleration: &AccelerationFn) -> Vec<State> {
+    let accelerations = evaluate(states, acceleration);
+
+    states
+        .iter()
+        .zip(accelerations.iter())
+        .map(|(state, a)| State {
+            position: state.velocity,
+            velocity: a.linear,
+            orientation: state.angular_velocity,
+            angular_velocity: a.angular,
+        })
+        .collect()
+}
+
+/// Step each state along its derivative by `dt`
+fn offset(states: &[State], derivatives: &[State], dt: f32) -> Vec<State> {
+    states
+        .iter()
+        .zip(derivatives.iter())
+        .map(|(state, d)| State {
+            position: vector::add(state.position, vector::scale(d.position, dt)),
+            velocity: vector::add(state.velocity, vector::scale(d.velocity, dt)),
+            orientation: state.orientation + d.orientation * dt,
+            angular_velocity: state.angular_velocity + d.angular_velocity * dt,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// How well an integrator conserved energy over a run
+    struct EnergyDrift {
+        /// Relative energy change at the end of the run
+        final_drift: f32,
+        /// Largest relative energy change seen at any step
+        max_drift: f32,
+    }
+
+    /// Measure energy drift on an undamped unit mass spring
+    ///
+    /// The mass starts at rest one unit from the anchor of a spring with unit stiffness, so the exact solution is a circle in phase space with period 2π. A perfect integrator keeps the total energy constant.
+    fn spring_energy_drift(integrator: &dyn Integrator, steps: usize, dt: f32) -> EnergyDrift {
+        let stiffness = 1.0;
+        let energy = |state: &State| {
+            0.5 * vector::length_squared(state.velocity)
+                + 0.5 * stiffness * vector::length_squared(state.position)
+        };
+        let spring = |states: &[State], accelerations: &mut [Acceleration]| {
+            for (state, a) in states.iter().zip(accelerations.iter_mut()) {
+                a.linear = vector::scale(state.position, -stiffness);
+            }
+        };
+
+        let mut states = [State {
+            position: (1.0, 0.0),
+            ..Default::default()
+        }];
+
+        let initial_energy = energy(&states[0]);
+        let mut max_drift: f32 = 0.0;
+
+        for _ in 0..steps {
+            integrator.integrate(&mut states, dt, &spring);
+
+            let drift = (energy(&states[0]) - initial_energy) / initial_energy;
+            max_drift = max_drift.max(drift.abs());
+        }
+
+        EnergyDrift {
+            final_drift: (energy(&states[0]) - initial_energy) / initial_energy,
+            max_drift,
+        }
+    }
+
+    /// Drift over 10000 steps of 0.01 s, about 16 periods of the spring
+    fn drift(integrator: &dyn Integrator) -> EnergyDrift {
+        spring_energy_drift(integrator, 10_000, 0.01)
+    }
+
+    #[test]
+    fn explicit_euler_gains_energy() {
+        let drift = drift(&ExplicitEuler);
+        assert!(drift.final_drift > 1.0, "final drift {}", drift.final_drift);
+    }
+
+    #[test]
+    fn semi_implicit_euler_energy_stays_bounded() {
+        let drift = drift(&SemiImplicitEuler);
+        assert!(drift.max_drift < 1e-2, "max drift {}", drift.max_drift);
+        assert!(
+            drift.final_drift.abs() < 1e-2,
+            "final drift {}",
+            drift.final_drift
+        );
+    }
+
+    #[test]
+    fn velocity_verlet_conserves_energy() {
+        let drift = drift(&VelocityVerlet);
+        assert!(drift.max_drift < 1e-4, "max drift {}", drift.max_drift);
+    }
+
+    #[test]
+    fn runge_kutta_4_conserves_energy() {
+        let drift = drift(&RungeKutta4);
+        assert!(drift.max_drift < 1e-4, "max drift {}", drift.max_drift);
+    }
+}
@@ -0,0 +1,661 @@
+//! Connections that hold pairs of bodies together
+//!
+//! Each joint becomes a few scalar constraints on the relative motion of its two bodies, called rows. The contact solver sweeps over the rows together with the contacts, so a chain hanging onto a pile of boxes settles as one system.
+
+use serde::{Deserialize, Serialize};
+
+use super::slot_map::Key;
+use super::{inverse_or_zero, pair_mut, vector, Body, BodyId, Pose};
+
+/// A unique identifier for a joint in the physics engine
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct JointId(pub(super) Key);
+
+/// A spring and damper letting a distance joint stretch
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Spring {
+    /// Force per unit of stretch
+    pub stiffness: f32,
+    /// Force per unit of stretching speed
+    pub damping: f32,
+}
+
+/// The range a joint's angle or translation is kept within
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Limits {
+    pub lower: f32,
+    pub upper: f32,
+}
+
+/// Drives a joint toward a target speed with limited strength
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Motor {
+    /// Target speed in radians per second for revolute joints, or units per second for prismatic joints
+    pub speed: f32,
+    /// The largest torque for revolute joints, or force for prismatic joints, the motor can apply
+    pub max_force: f32,
+}
+
+/// How a joint constrains its two bodies
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum JointKind {
+    /// Keeps the anchors a fixed distance apart like a rigid rod, or pulls them back toward it like a spring when `spring` is given
+    ///
+    /// A length of zero leaves the joint without a direction to push along, so use a revolute joint to pin points together.
+    Distance {
+        length: f32,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        spring: Option<Spring>,
+    },
+
+    /// Pins the anchors together while the bodies turn freely about them, like a hinge
+    ///
+    /// Limits and motor act on the angle of the second body relative to the first.
+    Revolute {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limits: Option<Limits>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        motor: Option<Motor>,
+    },
+
+    /// Lets the second body slide along an axis fixed to the first without turning relative to it
+    ///
+    /// The axis is in the first body's own coordinates. Limits and motor act on how far the second anchor has moved along the axis from the first.
+    Prismatic {
+        axis: (f32, f32),
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        limits: Option<Limits>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        motor: Option<Motor>,
+    },
+}
+
+/// A connection between two bodies
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Joint {
+    pub id: JointId,
+    pub a: BodyId,
+    pub b: BodyId,
+
+    /// Where the joint attaches to each body, relative to the body position before rotation
+    pub local_anchor_a: (f32, f32),
+    pub local_anchor_b: (f32, f32),
+
+    /// The orientation of the second body relative to the first at which the joint angle is zero
+    pub reference_angle: f32,
+
+    pub kind: JointKind,
+
+    /// Whether the two bodies still collide with each other. Off by default, since linked bodies usually overlap at the anchors.
+    pub collide_connected: bool,
+
+    /// Impulses accumulated by each row in the last update, for warm starting
+    impulses: Vec<f32>,
+}
+
+impl Joint {
+    pub(super) fn new(
+        id: JointId,
+        a: &Body,
+        b: &Body,
+        anchor_a: (f32, f32),
+        anchor_b: (f32, f32),
+        kind: JointKind,
+    ) -> Joint {
+        let local = |body: &Body, anchor| {
+            vector::rotate(
+                vector::sub(anchor, body.pose.position),
+                -body.pose.orientation,
+            )
+        };
+
+        Joint {
+            id,
+            a: a.id,
+            b: b.id,
+            local_anchor_a: local(a, anchor_a),
+            local_anchor_b: local(b, anchor_b),
+            reference_angle: b.pose.orientation - a.pose.orientation,
+            kind,
+            collide_connected: false,
+            impulses: Vec::new(),
+        }
+    }
+
+    /// The first anchor in world coordinates for the first body at `pose`
+    pub fn anchor_a(&self, pose: &Pose) -> (f32, f32) {
+        vector::add(
+            pose.position,
+            vector::rotate(self.local_anchor_a, pose.orientation),
+        )
+    }
+
+    /// The second anchor in world coordinates for the second body at `pose`
+    pub fn anchor_b(&self, pose: &Pose) -> (f32, f32) {
+        vector::add(
+            pose.position,
+            vector::rotate(self.local_anchor_b, pose.orientation),
+        )
+    }
+
+    /// Work out the rows for the current poses, starting from last update's impulses if warm starting
+    pub(super) fn prepare(
+        &self,
+        bodies: &[Body],
+        a: usize,
+        b: usize,
+        warm_starting: bool,
+        correction_factor: f32,
+        dt: f32,
+    ) -> JointConstraint {
+        let (body_a, body_b) = (&bodies[a], &bodies[b]);
+        let r_a = vector::rotate(self.local_anchor_a, body_a.pose.orientation);
+        let r_b = vector::rotate(self.local_anchor_b, body_b.pose.orientation);
+        let separation = vector::sub(
+            vector::add(body_b.pose.position, r_b),
+            vector::add(body_a.pose.position, r_a),
+        );
+        let angle = body_b.pose.orientation - body_a.pose.orientation - self.reference_angle;
+
+        // Drift is fed back into the velocity like Baumgarte contact correction. Joints hold in both directions, so the extra speed is cancelled next update rather than pushing the bodies apart.
+        let rigid = |row: Row, error: f32| row.with_bias(correction_factor / dt * error);
+        let angular = || Row::new(body_a, body_b, (0.0, 0.0), 1.0, 1.0);
+
+        let mut rows = Vec::new();
+        let driven = |row, value, limits, motor| {
+            driven_rows(row, value, limits, motor, correction_factor, dt)
+        };
+
+        match self.kind {
+            JointKind::Distance { length, spring } => {
+                let direction = vector::normalize(separation);
+                let row = Row::new(
+                    body_a,
+                    body_b,
+                    direction,
+                    vector::cross(r_a, direction),
+                    vector::cross(r_b, direction),
+                );
+                let error = vector::length(separation) - length;
+
+                match spring {
+                    Some(spring) if spring.stiffness > 0.0 || spring.damping > 0.0 => {
+                        rows.push(row.soft(error, spring, dt))
+                    }
+                    Some(_) => {}
+                    None => rows.push(rigid(row, error)),
+                }
+            }
+            JointKind::Revolute { limits, motor } => {
+                for axis in [(1.0, 0.0), (0.0, 1.0)] {
+                    let row = Row::new(
+                        body_a,
+                        body_b,
+                        axis,
+                        vector::cross(r_a, axis),
+                        vector::cross(r_b, axis),
+                    );
+                    rows.push(rigid(row, vector::dot(separation, axis)));
+                }
+                rows.extend(driven(angular(), angle, limits, motor));
+            }
+            JointKind::Prismatic {
+                axis,
+                limits,
+                motor,
+            } => {
+                let axis = vector::normalize(vector::rotate(axis, body_a.pose.orientation));
+                let normal = (-axis.1, axis.0);
+
+                // The first body's offset is measured to the second anchor, since the axis turns with the first body
+                let arm_a = vector::add(separation, r_a);
+                let perpendicular = Row::new(
+                    body_a,
+                    body_b,
+                    normal,
+                    vector::cross(arm_a, normal),
+                    vector::cross(r_b, normal),
+                );
+                rows.push(rigid(perpendicular, vector::dot(separation, normal)));
+                rows.push(rigid(angular(), angle));
+
+                let along = Row::new(
+                    body_a,
+                    body_b,
+                    axis,
+                    vector::cross(arm_a, axis),
+                    vector::cross(r_b, axis),
+                );
+                rows.extend(driven(along, vector::dot(separation, axis), limits, motor));
+            }
+        }
+
+        if warm_starting && self.impulses.len() == rows.len() {
+            for (row, impulse) in rows.iter_mut().zip(&self.impulses) {
+                row.impulse = impulse.clamp(row.min_impulse, row.max_impulse);
+            }
+        }
+
+        JointConstraint { a, b, rows }
+    }
+
+    /// Keep this update's impulses for warm starting the next
+    pub(super) fn store_impulses(&mut self, constraint: &JointConstraint) {
+        self.impulses = constraint.rows.iter().map(|row| row.impulse).collect();
+    }
+}
+
+/// The motor and limit rows for a joint angle or translation of `value`, acting along `row`
+fn driven_rows(
+    row: Row,
+    value: f32,
+    limits: Option<Limits>,
+    motor: Option<Motor>,
+    correction_factor: f32,
+    dt: f32,
+) -> Vec<Row> {
+    let mut rows = Vec::new();
+    if let Some(motor) = motor {
+        let max_impulse = motor.max_force * dt;
+        rows.push(Row {
+            bias: -motor.speed,
+            min_impulse: -max_impulse,
+            max_impulse,
+            ..row
+        });
+    }
+    if let Some(limits) = limits {
+        rows.push(row.limit(value - limits.lower, correction_factor, dt));
+        rows.push(
+            row.negated()
+                .limit(limits.upper - value, correction_factor, dt),
+        );
+    }
+    rows
+}
+
+/// One scalar constraint on the relative velocity of two bodies
+///
+/// The constrained speed is the linear velocity of the second body relative to the first along `linear`, plus each angular velocity weighted by its angular term, the first negated.
+#[derive(Clone, Copy)]
+struct Row {
+    linear: (f32, f32),
+    angular_a: f32,
+    angular_b: f32,
+
+    /// The inverse of the effective inverse mass along the row, including any softness
+    mass: f32,
+
+    /// Added to the constrained speed, so the row drives the speed toward its negation
+    bias: f32,
+
+    /// How much the accumulated impulse weakens the row, which makes it behave like a spring
+    softness: f32,
+
+    /// Bounds on the accumulated impulse. Rows that can only push have a lower bound of zero.
+    min_impulse: f32,
+    max_impulse: f32,
+
+    impulse: f32,
+}
+
+impl Row {
+    fn new(a: &Body, b: &Body, linear: (f32, f32), angular_a: f32, angular_b: f32) -> Row {
+        let inverse_mass = (a.dynamics.inverse_mass + b.dynamics.inverse_mass)
+            * vector::length_squared(linear)
+            + a.dynamics.inverse_inertia * angular_a * angular_a
+            + b.dynamics.inverse_inertia * angular_b * angular_b;
+
+        Row {
+            linear,
+            angular_a,
+            angular_b,
+            mass: inverse_or_zero(inverse_mass),
+            bias: 0.0,
+            softness: 0.0,
+            min_impulse: f32::NEG_INFINITY,
+            max_impulse: f32::INFINITY,
+            impulse: 0.0,
+        }
+    }
+
+    fn with_bias(self, bias: f32) -> Row {
+        Row { bias, ..self }
+    }
+
+    /// The same row acting in the opposite direction
+    fn negated(self) -> Row {
+        Row {
+            linear: vector::scale(self.linear, -1.0),
+            angular_a: -self.angular_a,
+            angular_b: -self.angular_b,
+            ..self
+        }
+    }
+
+    /// Keep `distance`, the distance to a limit, from going below zero
+    ///
+    /// While the limit is still ahead the row lets the bodies close exactly that gap in one update, so they stop at the limit rather than bouncing off it.
+    fn limit(self, distance: f32, correction_factor: f32, dt: f32) -> Row {
+        let bias = if distance > 0.0 {
+            distance / dt
+        } else {
+            correction_factor / dt * distance
+        };
+
+        Row {
+            bias,
+            min_impulse: 0.0,
+            ..self
+        }
+    }
+
+    /// Turn a rigid row into a spring and damper stretched by `error`
+    ///
+    /// The spring is solved implicitly, so it stays stable however stiff it is, though it loses a little energy every update even without damping.
+    fn soft(self, error: f32, spring: Spring, dt: f32) -> Row {
+        let softness = inverse_or_zero(dt * (spring.damping + dt * spring.stiffness));
+        let inverse_mass = inverse_or_zero(self.mass);
+
+        Row {
+            mass: inverse_or_zero(inverse_mass + softness),
+            bias: error * dt * spring.stiffness * softness,
+            softness,
+            ..self
+        }
+    }
+
+    /// The constrained speed at the current velocities
+    fn speed(&self, a: &Body, b: &Body) -> f32 {
+        vector::dot(
+            self.linear,
+            vector::sub(b.dynamics.velocity, a.dynamics.velocity),
+        ) + self.angular_b * b.dynamics.angular_velocity
+            - self.angular_a * a.dynamics.angular_velocity
+    }
+
+    fn apply(&self, a: &mut Body, b: &mut Body, impulse: f32) {
+        a.dynamics.velocity = vector::sub(
+            a.dynamics.velocity,
+            vector::scale(self.linear, a.dynamics.inverse_mass * impulse),
+        );
+        a.dynamics.angular_velocity -= a.dynamics.inverse_inertia * self.angular_a * impulse;
+
+        b.dynamics.velocity = vector::add(
+            b.dynamics.velocity,
+            vector::scale(self.linear, b.dynamics.inverse_mass * impulse),
+        );
+        b.dynamics.angular_velocity += b.dynamics.inverse_inertia * self.angular_b * impulse;
+    }
+}
+
+/// A joint prepared for solving
+pub(super) struct JointConstraint {
+    a: usize,
+    b: usize,
+    rows: Vec<Row>,
+}
+
+impl JointConstraint {
+    /// Apply the impulses carried over from the last update
+    pub fn warm_start(&self, bodies: &mut [Body]) {
+        let (a, b) = bodies_mut(bodies, self.a, self.b);
+        for row in &self.rows {
+            row.apply(a, b, row.impulse);
+        }
+    }
+
+    /// One sweep over every row of the joint
+    pub fn solve_velocity(&mut self, bodies: &mut [Body]) {
+        let (a, b) = bodies_mut(bodies, self.a, self.b);
+        for row in self.rows.iter_mut() {
+            let lambda = -row.mass * (row.speed(a, b) + row.bias + row.softness * row.impulse);
+
+            let previous = row.impulse;
+            row.impulse = (previous + lambda).clamp(row.min_impulse, row.max_impulse);
+            row.apply(a, b, row.impulse - previous);
+        }
+    }
+}
+
+/// Borrow the two bodies of a joint mutably, in either order
+fn bodies_mut(bodies: &mut [Body], a: usize, b: usize) -> (&mut Body, &mut Body) {
+    if a < b {
+        pair_mut(bodies, a, b)
+    } else {
+        let (b, a) = pair_mut(bodies, b, a);
+        (a, b)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::{Circle, PhysicsEngine, Polygon};
+    use super::*;
+
+    const DT: f32 = 1.0 / 60.0;
+
+    /// A static circle to hang things from
+    fn anchor(engine: &mut PhysicsEngine, position: (f32, f32)) -> BodyId {
+        let body = engine.add_object(Circle {
+            origin: (0.0, 0.0),
+            radius: 0.1,
+        });
+        body.set_position(position);
+        body.dynamics.set_static();
+        body.id
+    }
+
+    fn ball(engine: &mut PhysicsEngine, position: (f32, f32)) -> BodyId {
+        let body = engine.add_object(Circle {
+            origin: (0.0, 0.0),
+            radius: 0.2,
+        });
+        body.set_position(position);
+        body.id
+    }
+
+    /// Step the engine `steps` times, calling `check` with the engine after each step
+    fn run(engine: &mut PhysicsEngine, steps: usize, mut check: impl FnMut(&PhysicsEngine)) {
+        for _ in 0..steps {
+            engine.update(Duration::from_secs_f32(DT));
+            check(engine);
+        }
+    }
+
+    #[test]
+    fn pendulum_keeps_its_length() {
+        let mut engine = PhysicsEngine::new();
+        engine.gravity = (0.0, -10.0);
+        let pivot = anchor(&mut engine, (0.0, 0.0));
+        let bob = ball(&mut engine, (2.0, 0.0));
+        let kind = JointKind::Distance {
+            length: 2.0,
+            spring: None,
+        };
+        engine
+            .add_joint(pivot, bob, (0.0, 0.0), (2.0, 0.0), kind)
+            .unwrap();
+
+        let mut lowest = f32::MAX;
+        run(&mut engine, 300, |engine| {
+            let position = engine.get_object(bob).unwrap().pose.position;
+            let length = vector::length(position);
+            assert!(
+                (length - 2.0).abs() < 0.02,
+                "pendulum stretched to {length}"
+            );
+            lowest = lowest.min(position.1);
+        });
+
+        // It really swung rather than being held in place
+        assert!(lowest < -1.9, "the bob only fell to {lowest}");
+    }
+
+    #[test]
+    fn revolute_limits_hold_the_angle() {
+        let mut engine = PhysicsEngine::new();
+        engine.gravity = (0.0, -10.0);
+        let pivot = anchor(&mut engine, (0.0, 0.0));
+        let bar = engine
+            .add_object(Polygon::new_box((1.0, 0.0), (1.0, 0.1)))
+            .id;
+        let kind = JointKind::Revolute {
+            limits: Some(Limits {
+                lower: -0.5,
+                upper: 0.5,
+            }),
+            motor: None,
+        };
+        let joint = engine
+            .add_joint(pivot, bar, (0.0, 0.0), (0.0, 0.0), kind)
+            .unwrap()
+            .id;
+
+        run(&mut engine, 300, |engine| {
+            let angle = engine.get_object(bar).unwrap().pose.orientation;
+            assert!(angle > -0.55, "the bar swung down to {angle}");
+        });
+
+        // Gravity holds it against the lower limit, still pinned at the pivot
+        let bar = engine.get_object(bar).unwrap();
+        let joint = engine.get_joint(joint).unwrap();
+        let pin = joint.anchor_b(&bar.pose);
+        assert!(
+            (bar.pose.orientation + 0.5).abs() < 0.02,
+            "the bar rests at {}",
+            bar.pose.orientation
+        );
+        assert!(vector::length(pin) < 0.01, "the pin drifted to {pin:?}");
+    }
+
+    #[test]
+    fn motor_reaches_its_target_speed() {
+        let mut engine = PhysicsEngine::new();
+        let axle = anchor(&mut engine, (0.0, 0.0));
+        let wheel = ball(&mut engine, (0.0, 0.0));
+        let inverse_inertia = engine.get_object(wheel).unwrap().dynamics.inverse_inertia();
+
+        // Strong enough to reach full speed in half a second
+        let motor = Motor {
+            speed: 3.0,
+            max_force: 3.0 / 0.5 / inverse_inertia,
+        };
+        let kind = JointKind::Revolute {
+            limits: None,
+            motor: Some(motor),
+        };
+        engine
+            .add_joint(axle, wheel, (0.0, 0.0), (0.0, 0.0), kind)
+            .unwrap();
+
+        // The torque is limited, so the wheel spins up gradually
+        let most_per_step = motor.max_force * DT * inverse_inertia;
+        let mut previous = 0.0;
+        run(&mut engine, 120, |engine| {
+            let speed = engine.get_object(wheel).unwrap().dynamics.angular_velocity;
+            assert!(
+                speed - previous <= most_per_step * 1.01,
+                "sped up from {previous} to {speed} in one step"
+            );
+            previous = speed;
+        });
+
+        let wheel = engine.get_object(wheel).unwrap();
+        assert!(
+            (wheel.dynamics.angular_velocity - motor.speed).abs() < 1e-3,
+            "the wheel spins at {}",
+            wheel.dynamics.angular_velocity
+        );
+        assert!(vector::length(wheel.pose.position) < 1e-3);
+    }
+
+    #[test]
+    fn prismatic_joint_only_slides_along_its_axis() {
+        let mut engine = PhysicsEngine::new();
+        engine.gravity = (0.0, -10.0);
+        let rail = anchor(&mut engine, (0.0, 0.0));
+        let slider = engine.add_object(Polygon::new_box((0.0, 0.0), (0.3, 0.3)));
+        slider.dynamics.velocity = (2.0, 0.0);
+        slider.dynamics.angular_velocity = 1.0;
+        let slider = slider.id;
+        let kind = JointKind::Prismatic {
+            axis: (1.0, 0.0),
+            limits: Some(Limits {
+                lower: -1.0,
+                upper: 1.0,
+            }),
+            motor: None,
+        };
+        engine
+            .add_joint(rail, slider, (0.0, 0.0), (0.0, 0.0), kind)
+            .unwrap();
+
+        let mut furthest = 0.0f32;
+        run(&mut engine, 120, |engine| {
+            let pose = engine.get_object(slider).unwrap().pose;
+            assert!(
+                pose.position.1.abs() < 0.01,
+                "the slider fell to {:?}",
+                pose.position
+            );
+            assert!(
+                pose.orientation.abs() < 0.01,
+                "the slider turned to {}",
+                pose.orientation
+            );
+            furthest = furthest.max(pose.position.0);
+        });
+
+        // It slid freely along the axis until the upper limit stopped it, within the last step's motion since the limit is only seen once it is about to be passed
+        let last_step = 2.0 * DT;
+        assert!(
+            furthest <= 1.0 + 0.01 && furthest >= 1.0 - last_step - 0.01,
+            "the slider reached {furthest}"
+        );
+    }
+
+    #[test]
+    fn soft_distance_joint_stretches_like_a_spring() {
+        let mut engine = PhysicsEngine::new();
+        engine.gravity = (0.0, -10.0);
+        let ceiling = anchor(&mut engine, (0.0, 0.0));
+        let weight = ball(&mut engine, (0.0, -1.0));
+        engine
+            .get_object_mut(weight)
+            .unwrap()
+            .dynamics
+            .set_mass(1.0);
+        let spring = Spring {
+            stiffness: 20.0,
+            damping: 2.0,
+        };
+        let kind = JointKind::Distance {
+            length: 1.0,
+            spring: Some(spring),
+        };
+        engine
+            .add_joint(ceiling, weight, (0.0, 0.0), (0.0, -1.0), kind)
+            .unwrap();
+
+        // The weight bounces below where it hangs before settling
+        let hanging = 1.0 + 10.0 / spring.stiffness;
+        let mut lowest = 0.0f32;
+        run(&mut engine, 600, |engine| {
+            lowest = lowest.min(engine.get_object(weight).unwrap().pose.position.1);
+        });
+        assert!(
+            -lowest > hanging + 0.2 * (hanging - 1.0),
+            "the weight only dropped to {lowest}, hanging at {hanging}"
+        );
+
+        let settled = -engine.get_object(weight).unwrap().pose.position.1;
+        assert!(
+            (settled - hanging).abs() < 0.01 * hanging,
+            "the weight hangs at {settled}, expected {hanging}"
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! What bodies are made of, deciding how they bounce, slide and how heavy they are
+
+use serde::{Deserialize, Serialize};
+
+/// The surface and bulk properties of a body
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub struct Material {
+    /// How much speed is kept in a collision. 1.0 is perfectly elastic and 0.0 is perfectly inelastic.
+    pub restitution: f32,
+
+    /// Friction coefficient that has to be overcome before a resting contact starts to slide
+    pub static_friction: f32,
+
+    /// Friction coefficient while the contact is sliding. Usually a little below the static coefficient.
+    pub dynamic_friction: f32,
+
+    /// Mass per unit area
+    pub density: f32,
+}
+
+impl Default for Material {
+    fn default() -> Material {
+        Material::DEFAULT
+    }
+}
+
+impl Material {
+    /// Moderately bouncy with some friction, at unit density
+    pub const DEFAULT: Material = Material {
+        restitution: 0.8,
+        static_friction: 0.3,
+        dynamic_friction: 0.3,
+        density: 1.0,
+    };
+
+    pub const RUBBER: Material = Material {
+        restitution: 0.85,
+        static_friction: 1.0,
+        dynamic_friction: 0.8,
+        density: 1.1,
+    };
+
+    pub const ICE: Material = Material {
+        restitution: 0.1,
+        static_friction: 0.05,
+        dynamic_friction: 0.02,
+        density: 0.92,
+    };
+
+    pub const STEEL: Material = Material {
+        restitution: 0.6,
+        static_friction: 0.75,
+        dynamic_friction: 0.55,
+        density: 7.85,
+    };
+
+    pub const WOOD: Material = Material {
+        restitution: 0.45,
+        static_friction: 0.5,
+        dynamic_friction: 0.4,
+        density: 0.6,
+    };
+
+    pub const STONE: Material = Material {
+        restitution: 0.2,
+        static_friction: 0.7,
+        dynamic_friction: 0.6,
+        density: 2.5,
+    };
+
+    /// Every built-in material with the name scenes use for it
+    pub const PRESETS: [(&'static str, Material); 6] = [
+        ("default", Material::DEFAULT),
+        ("rubber", Material::RUBBER),
+        ("ice", Material::ICE),
+        ("steel", Material::STEEL),
+        ("wood", Material::WOOD),
+        ("stone", Material::STONE),
+    ];
+
+    /// Look up a built-in material by name
+    pub fn preset(name: &str) -> Option<Material> {
+        Material::PRESETS
+            .iter()
+            .find(|(preset_name, _)| *preset_name == name)
+            .map(|&(_, material)| material)
+    }
+}
+
+/// How the coefficients of two touching materials are combined into one for the contact
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CombineRule {
+    #[default]
+    Average,
+    /// The smaller coefficient, so the slipperier or deader material wins
+    Min,
+    /// The larger coefficient, so the grippier or bouncier material wins
+    Max,
+    /// The product, which makes a contact weak if either material is
+    Multiply,
+}
+
+impl CombineRule {
+    pub fn combine(self, a: f32, b: f32) -> f32 {
+        match self {
+            CombineRule::Average => (a + b) / 2.0,
+            CombineRule::Min => a.min(b),
+            CombineRule::Max => a.max(b),
+            CombineRule::Multiply => a * b,
+        }
+    }
+}
+
+/// The coefficients used for a contact between two materials
+pub(super) struct ContactMaterial {
+    pub restitution: f32,
+    pub static_friction: f32,
+    pub dynamic_friction: f32,
+}
+
+impl ContactMaterial {
+    pub fn between(
+        a: &Material,
+        b: &Material,
+        restitution_rule: CombineRule,
+        friction_rule: CombineRule,
+    ) -> ContactMaterial {
+        ContactMaterial {
+            restitution: restitution_rule.combine(a.restitution, b.restitution),
+            static_friction: friction_rule.combine(a.static_friction, b.static_friction),
+            dynamic_friction: friction_rule.combine(a.dynamic_friction, b.dynamic_friction),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RULES: [CombineRule; 4] = [
+        CombineRule::Average,
+        CombineRule::Min,
+        CombineRule::Max,
+        CombineRule::Multiply,
+    ];
+
+    #[test]
+    fn combine_rules_give_the_expected_coefficient() {
+        let expected = [0.5, 0.2, 0.8, 0.16];
+        for (rule, expected) in RULES.into_iter().zip(expected) {
+            let combined = rule.combine(0.2, 0.8);
+            assert!(
+                (combined - expected).abs() < 1e-6,
+                "{rule:?} gave {combined}, expected {expected}"
+            );
+            assert_eq!(
+                combined,
+                rule.combine(0.8, 0.2),
+                "{rule:?} is not symmetric"
+            );
+        }
+    }
+
+    #[test]
+    fn combine_rules_are_named_in_snake_case() {
+        assert_eq!(
+            serde_json::to_string(&CombineRule::Multiply).unwrap(),
+            r#""multiply""#
+        );
+        let rule: CombineRule = serde_json::from_str(r#""min""#).unwrap();
+        assert_eq!(rule, CombineRule::Min);
+    }
+
+    #[test]
+    fn contact_combines_restitution_and_friction_separately() {
+        let contact = ContactMaterial::between(
+            &Material::RUBBER,
+            &Material::ICE,
+            CombineRule::Max,
+            CombineRule::Min,
+        );
+        assert_eq!(contact.restitution, Material::RUBBER.restitution);
+        assert_eq!(contact.static_friction, Material::ICE.static_friction);
+        assert_eq!(contact.dynamic_friction, Material::ICE.dynamic_friction);
+    }
+
+    #[test]
+    fn presets_are_found_by_name() {
+        for (name, material) in Material::PRESETS {
+            assert_eq!(Material::preset(name), Some(material));
+        }
+        assert_eq!(Material::preset("steel"), Some(Material::STEEL));
+        assert_eq!(Material::preset("Steel"), None);
+        assert_eq!(Material::preset("unobtainium"), None);
+    }
+
+    #[test]
+    fn presets_are_physically_plausible() {
+        for (name, material) in Material::PRESETS {
+            assert!((0.0..=1.0).contains(&material.restitution), "{name}");
+            assert!(material.dynamic_friction >= 0.0, "{name}");
+            assert!(
+                material.dynamic_friction <= material.static_friction,
+                "{name} slides with more friction than it grips"
+            );
+            assert!(material.density > 0.0, "{name}");
+        }
+    }
+}
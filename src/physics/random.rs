@@ -0,0 +1,22 @@
+//! A seeded random number generator for repeatable test scenes and benchmarks
+//!
+//! Xorshift is a poor generator for anything serious, but it needs no dependencies and gives the same sequence on every machine, so scenes built from it are identical from run to run.
+
+/// A 32-bit xorshift generator
+pub struct Xorshift(u32);
+
+impl Xorshift {
+    /// Start a sequence from a seed, which must not be zero
+    pub fn new(seed: u32) -> Xorshift {
+        assert_ne!(seed, 0, "a xorshift generator never leaves a zero seed");
+        Xorshift(seed)
+    }
+
+    /// The next number in the sequence, from 0.0 to 1.0
+    pub fn next_f32(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32
+    }
+}
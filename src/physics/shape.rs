@@ -0,0 +1,604 @@
+//! Collision shapes attached to physics bodies
+//!
+//! Shape coordinates are relative to the position of the body they belong to and rotate with it. Bodies turn about their position, so the engine moves each new body's shape to put its center of mass on the body position.
+
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
+
+use super::collision::closest_point_on_segment;
+use super::vector;
+use super::Pose;
+
+/// A physics circle primitive
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Circle {
+    pub origin: (f32, f32),
+    pub radius: f32,
+}
+
+/// A physics rectangle primitive with a center origin
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Rectangle {
+    pub origin: (f32, f32),
+    pub half_extents: (f32, f32),
+}
+
+/// A line segment swept by a circle, giving a rectangle with rounded ends
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Capsule {
+    pub start: (f32, f32),
+    pub end: (f32, f32),
+    pub radius: f32,
+}
+
+/// A line segment with no thickness
+///
+/// Segments have no area, so a body with only a segment shape has no mass and cannot be moved by forces. They are best suited to static scenery such as floors and walls.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Segment {
+    pub start: (f32, f32),
+    pub end: (f32, f32),
+}
+
+/// A convex polygon
+///
+/// Vertices are stored in counter-clockwise order along with the outward normal of the edge that starts at each vertex. Only the vertices are serialized, and they go through `Polygon::new` again when deserialized, so a saved polygon can never skip its checks.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(try_from = "PolygonPoints", into = "PolygonPoints")]
+pub struct Polygon {
+    vertices: Vec<(f32, f32)>,
+    normals: Vec<(f32, f32)>,
+}
+
+/// The serialized form of a polygon
+#[derive(Serialize, Deserialize)]
+struct PolygonPoints {
+    vertices: Vec<(f32, f32)>,
+}
+
+impl TryFrom<PolygonPoints> for Polygon {
+    type Error = &'static str;
+
+    fn try_from(points: PolygonPoints) -> Result<Polygon, Self::Error> {
+        Polygon::try_new(&points.vertices).ok_or(NOT_A_POLYGON)
+    }
+}
+
+impl From<Polygon> for PolygonPoints {
+    fn from(polygon: Polygon) -> PolygonPoints {
+        PolygonPoints {
+            vertices: polygon.vertices,
+        }
+    }
+}
+
+const NOT_A_POLYGON: &str = "a polygon needs at least three points that are not on one line";
+
+impl Polygon {
+    /// Create a polygon from the convex hull of a set of points
+    ///
+    /// The points may be in any order, and points inside the hull are dropped. At least three points that are not all on one line are required.
+    pub fn new(points: &[(f32, f32)]) -> Polygon {
+        Polygon::try_new(points).expect(NOT_A_POLYGON)
+    }
+
+    /// Create a polygon from the convex hull of a set of points, or `None` if there are fewer than three points that are not all on one line or any are not finite
+    pub fn try_new(points: &[(f32, f32)]) -> Option<Polygon> {
+        if points
+            .iter()
+            .any(|point| !point.0.is_finite() || !point.1.is_finite())
+        {
+            return None;
+        }
+
+        let vertices = convex_hull(points);
+        if vertices.len() < 3 {
+            return None;
+        }
+
+        let normals = edge_normals(&vertices);
+
+        Some(Polygon { vertices, normals })
+    }
+
+    /// Create an axis-aligned box
+    pub fn new_box(center: (f32, f32), half_extents: (f32, f32)) -> Polygon {
+        Polygon::new(&box_vertices(center, half_extents))
+    }
+
+    /// Vertices in counter-clockwise order
+    pub fn vertices(&self) -> &[(f32, f32)] {
+        &self.vertices
+    }
+
+    /// The outward unit normal of the edge from each vertex to the next
+    pub fn normals(&self) -> &[(f32, f32)] {
+        &self.normals
+    }
+}
+
+/// The four corners of an axis-aligned box in counter-clockwise order
+fn box_vertices(center: (f32, f32), half_extents: (f32, f32)) -> [(f32, f32); 4] {
+    let (x, y) = center;
+    let (w, h) = half_extents;
+    [
+        (x - w, y - h),
+        (x + w, y - h),
+        (x + w, y + h),
+        (x - w, y + h),
+    ]
+}
+
+/// The outward normal of the edge from each vertex to the next, for vertices in counter-clockwise order
+///
+/// Two vertices give the two opposite normals of a segment.
+fn edge_normals(vertices: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    (0..vertices.len())
+        .map(|i| {
+            let edge = vector::sub(vertices[(i + 1) % vertices.len()], vertices[i]);
+            vector::normalize((edge.1, -edge.0))
+        })
+        .collect()
+}
+
+/// The counter-clockwise convex hull of a set of points using the monotone chain algorithm
+fn convex_hull(points: &[(f32, f32)]) -> Vec<(f32, f32)> {
+    let mut sorted = points.to_vec();
+    sorted.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.total_cmp(&b.1)));
+    sorted.dedup();
+
+    if sorted.len() < 3 {
+        return sorted;
+    }
+
+    // Build the lower hull left to right, then the upper hull right to left
+    let mut hull: Vec<(f32, f32)> = Vec::with_capacity(sorted.len() + 1);
+    for pass in [sorted.clone(), sorted.into_iter().rev().collect()] {
+        let start = hull.len();
+        for point in pass {
+            while hull.len() >= start + 2
+                && vector::cross(
+                    vector::sub(hull[hull.len() - 1], hull[hull.len() - 2]),
+                    vector::sub(point, hull[hull.len() - 2]),
+                ) <= 0.0
+            {
+                hull.pop();
+            }
+            hull.push(point);
+        }
+        // The last point of each chain is the first point of the next
+        hull.pop();
+    }
+
+    hull
+}
+
+/// An axis-aligned bounding box in world coordinates
+#[derive(Clone, Copy)]
+pub struct Aabb {
+    pub min: (f32, f32),
+    pub max: (f32, f32),
+}
+
+impl Aabb {
+    /// Whether two boxes overlap. Boxes that only touch count as overlapping.
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.min.0 <= other.max.0
+            && other.min.0 <= self.max.0
+            && self.min.1 <= other.max.1
+            && other.min.1 <= self.max.1
+    }
+
+    /// Whether a point is inside the box
+    pub fn contains(&self, point: (f32, f32)) -> bool {
+        point.0 >= self.min.0
+            && point.0 <= self.max.0
+            && point.1 >= self.min.1
+            && point.1 <= self.max.1
+    }
+}
+
+/// Mass, center of mass and moment of inertia of a shape
+#[derive(Clone, Copy)]
+pub struct MassProperties {
+    pub mass: f32,
+    /// The center of mass in shape coordinates
+    pub center: (f32, f32),
+    /// Moment of inertia about the center of mass
+    pub inertia: f32,
+}
+
+/// The collision shape of a body
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Shape {
+    Circle(Circle),
+    Rectangle(Rectangle),
+    Polygon(Polygon),
+    Capsule(Capsule),
+    Segment(Segment),
+}
+
+/// A shape reduced to a convex core of one or more points swept by a radius
+///
+/// Every shape fits this form: a circle is one point with a radius, a segment is two points with no radius, a capsule is two points with a radius, and polygons are their vertices with no radius. Collision detection works on cores so it only needs to handle a few cases.
+pub(crate) struct Core {
+    /// Counter-clockwise vertices in world coordinates
+    pub vertices: Vec<(f32, f32)>,
+    /// The outward normal of the edge from each vertex to the next. Empty for a single point.
+    pub normals: Vec<(f32, f32)>,
+    pub radius: f32,
+}
+
+impl Shape {
+    /// The area covered by the shape
+    pub fn area(&self) -> f32 {
+        match self {
+            Shape::Circle(circle) => PI * circle.radius * circle.radius,
+            Shape::Rectangle(rectangle) => {
+                4.0 * rectangle.half_extents.0 * rectangle.half_extents.1
+            }
+            Shape::Polygon(polygon) => polygon_area_and_second_moment(&polygon.vertices).0,
+            Shape::Capsule(capsule) => {
+                let length = vector::length(vector::sub(capsule.end, capsule.start));
+                2.0 * capsule.radius * length + PI * capsule.radius * capsule.radius
+            }
+            Shape::Segment(_) => 0.0,
+        }
+    }
+
+    /// The center of the area covered by the shape, or the middle of a segment, in shape coordinates
+    pub fn centroid(&self) -> (f32, f32) {
+        match self {
+            Shape::Circle(circle) => circle.origin,
+            Shape::Rectangle(rectangle) => rectangle.origin,
+            Shape::Polygon(polygon) => polygon_centroid(&polygon.vertices),
+            Shape::Capsule(capsule) => vector::scale(vector::add(capsule.start, capsule.end), 0.5),
+            Shape::Segment(segment) => vector::scale(vector::add(segment.start, segment.end), 0.5),
+        }
+    }
+
+    /// Mass, center of mass and moment of inertia about the center of mass for a shape of uniform density
+    pub fn mass_properties(&self, density: f32) -> MassProperties {
+        let mass = density * self.area();
+        let center = self.centroid();
+
+        let inertia = match self {
+            Shape::Circle(circle) => mass * circle.radius * circle.radius / 2.0,
+            Shape::Rectangle(rectangle) => {
+                let (w, h) = rectangle.half_extents;
+                mass * (w * w + h * h) / 3.0
+            }
+            Shape::Polygon(polygon) => {
+                let about_center: Vec<_> = polygon
+                    .vertices
+                    .iter()
+                    .map(|&vertex| vector::sub(vertex, center))
+                    .collect();
+                density * polygon_area_and_second_moment(&about_center).1
+            }
+            Shape::Capsule(capsule) => {
+                let length = vector::length(vector::sub(capsule.end, capsule.start));
+                let radius = capsule.radius;
+
+                // A rectangle between the end points plus a circle split across the two ends
+                let rectangle_mass = density * 2.0 * radius * length;
+                let circle_mass = density * PI * radius * radius;
+
+                let half_length = length / 2.0;
+                let cap_centroid = 4.0 * radius / (3.0 * PI);
+
+                let rectangle_inertia =
+                    rectangle_mass * (length * length + 4.0 * radius * radius) / 12.0;
+                let circle_inertia = circle_mass
+                    * (radius * radius / 2.0
+                        + half_length * half_length
+                        + 2.0 * half_length * cap_centroid);
+
+                rectangle_inertia + circle_inertia
+            }
+            Shape::Segment(_) => 0.0,
+        };
+
+        MassProperties {
+            mass,
+            center,
+            inertia,
+        }
+    }
+
+    /// Move the shape relative to the body position
+    pub fn translate(&mut self, offset: (f32, f32)) {
+        match self {
+            Shape::Circle(circle) => circle.origin = vector::add(circle.origin, offset),
+            Shape::Rectangle(rectangle) => rectangle.origin = vector::add(rectangle.origin, offset),
+            Shape::Polygon(polygon) => {
+                for vertex in polygon.vertices.iter_mut() {
+                    *vertex = vector::add(*vertex, offset);
+                }
+            }
+            Shape::Capsule(capsule) => {
+                capsule.start = vector::add(capsule.start, offset);
+                capsule.end = vector::add(capsule.end, offset);
+            }
+            Shape::Segment(segment) => {
+                segment.start = vector::add(segment.start, offset);
+                segment.end = vector::add(segment.end, offset);
+            }
+        }
+    }
+
+    /// The smallest axis-aligned box in world coordinates containing the shape
+    pub fn aabb(&self, pose: &Pose) -> Aabb {
+        let core = self.core(pose);
+
+        let mut min = (f32::MAX, f32::MAX);
+        let mut max = (f32::MIN, f32::MIN);
+        for &(x, y) in core.vertices.iter() {
+            min = (min.0.min(x), min.1.min(y));
+            max = (max.0.max(x), max.1.max(y));
+        }
+
+        Aabb {
+            min: (min.0 - core.radius, min.1 - core.radius),
+            max: (max.0 + core.radius, max.1 + core.radius),
+        }
+    }
+
+    /// The point of the shape furthest in a direction, in world coordinates
+    ///
+    /// The direction does not need to be normalized. When several points are equally far, any one of them may be returned.
+    pub fn support(&self, pose: &Pose, direction: (f32, f32)) -> (f32, f32) {
+        let core = self.core(pose);
+
+        let vertex = core
+            .vertices
+            .iter()
+            .copied()
+            .max_by(|a, b| vector::dot(*a, direction).total_cmp(&vector::dot(*b, direction)))
+            .unwrap();
+
+        vector::add(
+            vertex,
+            vector::scale(vector::normalize(direction), core.radius),
+        )
+    }
+
+    /// Whether a point in world coordinates is inside the shape. Points on the boundary count as inside.
+    pub fn contains_point(&self, pose: &Pose, point: (f32, f32)) -> bool {
+        let core = self.core(pose);
+
+        if core.vertices.len() == 1 {
+            return vector::length_squared(vector::sub(point, core.vertices[0]))
+                <= core.radius * core.radius;
+        }
+
+        // Inside a polygon the point is behind every edge. Segments and capsules have no inside of their own, since the two normals of a segment only rule out the points off its line.
+        let inside_core = core.vertices.len() > 2
+            && core
+                .vertices
+                .iter()
+                .zip(core.normals.iter())
+                .all(|(&vertex, &normal)| vector::dot(normal, vector::sub(point, vertex)) <= 0.0);
+        if inside_core {
+            return true;
+        }
+
+        // Otherwise it must be within the radius of an edge
+        (0..core.vertices.len()).any(|i| {
+            let a = core.vertices[i];
+            let b = core.vertices[(i + 1) % core.vertices.len()];
+            let closest = closest_point_on_segment(point, a, b);
+            vector::length_squared(vector::sub(point, closest)) <= core.radius * core.radius
+        })
+    }
+
+    /// Reduce the shape to its core in world coordinates
+    pub(crate) fn core(&self, pose: &Pose) -> Core {
+        let to_world =
+            |v: (f32, f32)| vector::add(pose.position, vector::rotate(v, pose.orientation));
+
+        let (vertices, radius): (Vec<(f32, f32)>, f32) = match self {
+            Shape::Circle(circle) => (vec![to_world(circle.origin)], circle.radius),
+            Shape::Rectangle(rectangle) => (
+                box_vertices(rectangle.origin, rectangle.half_extents)
+                    .into_iter()
+                    .map(to_world)
+                    .collect(),
+                0.0,
+            ),
+            Shape::Polygon(polygon) => (
+                polygon.vertices.iter().copied().map(to_world).collect(),
+                0.0,
+            ),
+            Shape::Capsule(capsule) => (
+                vec![to_world(capsule.start), to_world(capsule.end)],
+                capsule.radius,
+            ),
+            Shape::Segment(segment) => (vec![to_world(segment.start), to_world(segment.end)], 0.0),
+        };
+
+        let normals = if vertices.len() > 1 {
+            edge_normals(&vertices)
+        } else {
+            Vec::new()
+        };
+
+        Core {
+            vertices,
+            normals,
+            radius,
+        }
+    }
+}
+
+/// The area of a counter-clockwise polygon and its second moment of area about the origin
+fn polygon_area_and_second_moment(vertices: &[(f32, f32)]) -> (f32, f32) {
+    // Sum over the triangles formed by each edge and the origin
+    let mut area = 0.0;
+    let mut second_moment = 0.0;
+    for i in 0..vertices.len() {
+        let a = vertices[i];
+        let b = vertices[(i + 1) % vertices.len()];
+        let cross = vector::cross(a, b);
+
+        area += cross / 2.0;
+        second_moment += cross * (vector::dot(a, a) + vector::dot(a, b) + vector::dot(b, b)) / 12.0;
+    }
+
+    (area, second_moment)
+}
+
+/// The centroid of the area of a counter-clockwise polygon
+fn polygon_centroid(vertices: &[(f32, f32)]) -> (f32, f32) {
+    // Weight the centroid of each triangle formed by an edge and the origin by its area
+    let mut area = 0.0;
+    let mut moment = (0.0, 0.0);
+    for i in 0..vertices.len() {
+        let a = vertices[i];
+        let b = vertices[(i + 1) % vertices.len()];
+        let cross = vector::cross(a, b);
+
+        area += cross / 2.0;
+        moment = vector::add(moment, vector::scale(vector::add(a, b), cross / 6.0));
+    }
+
+    vector::scale(moment, 1.0 / area)
+}
+
+impl From<Circle> for Shape {
+    fn from(circle: Circle) -> Shape {
+        Shape::Circle(circle)
+    }
+}
+
+impl From<Rectangle> for Shape {
+    fn from(rectangle: Rectangle) -> Shape {
+        Shape::Rectangle(rectangle)
+    }
+}
+
+impl From<Polygon> for Shape {
+    fn from(polygon: Polygon) -> Shape {
+        Shape::Polygon(polygon)
+    }
+}
+
+impl From<Capsule> for Shape {
+    fn from(capsule: Capsule) -> Shape {
+        Shape::Capsule(capsule)
+    }
+}
+
+impl From<Segment> for Shape {
+    fn from(segment: Segment) -> Shape {
+        Shape::Segment(segment)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn polygon_round_trips_without_normals() {
+        let polygon = Polygon::new(&[(0.0, 0.0), (2.0, 0.0), (1.0, 2.0)]);
+        let json = serde_json::to_string(&polygon).unwrap();
+        assert!(!json.contains("normals"));
+
+        let restored: Polygon = serde_json::from_str(&json).unwrap();
+        assert_eq!(restored.vertices(), polygon.vertices());
+        assert_eq!(restored.normals(), polygon.normals());
+    }
+
+    #[test]
+    fn deserialized_polygon_is_made_convex() {
+        let json = r#"{"vertices": [[0, 0], [1, 1], [2, 0], [1, 2]], "normals": [[9, 9]]}"#;
+        let polygon: Polygon = serde_json::from_str(json).unwrap();
+        assert_eq!(polygon.vertices(), &[(0.0, 0.0), (2.0, 0.0), (1.0, 2.0)]);
+        assert_eq!(polygon.normals().len(), 3);
+    }
+
+    #[test]
+    fn deserializing_a_degenerate_polygon_fails() {
+        for json in [
+            r#"{"vertices": []}"#,
+            r#"{"vertices": [[0, 0], [1, 0]]}"#,
+            r#"{"vertices": [[0, 0], [1, 0], [2, 0]]}"#,
+        ] {
+            assert!(serde_json::from_str::<Polygon>(json).is_err(), "{json}");
+        }
+    }
+
+    /// A right triangle with legs of 3 and its centroid at (2, 2), away from its origin
+    fn off_center_triangle() -> Polygon {
+        Polygon::new(&[(1.0, 1.0), (4.0, 1.0), (1.0, 4.0)])
+    }
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            vector::length(vector::sub(actual, expected)) < 1e-4,
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn off_center_polygon_inertia_is_about_its_centroid() {
+        let triangle = Shape::Polygon(off_center_triangle());
+        let properties = triangle.mass_properties(2.0);
+
+        // A right triangle with legs a and b has m(a² + b²)/18 about its centroid
+        assert!((properties.mass - 9.0).abs() < 1e-4, "{}", properties.mass);
+        assert_close(properties.center, (2.0, 2.0));
+        assert!(
+            (properties.inertia - 9.0).abs() < 1e-3,
+            "{}",
+            properties.inertia
+        );
+    }
+
+    #[test]
+    fn off_center_body_spins_about_its_center_of_mass() {
+        let mut engine = super::super::PhysicsEngine::new();
+        let body = engine.add_object(off_center_triangle());
+        body.dynamics.angular_velocity = 3.0;
+        let id = body.id;
+
+        // The body starts at the centroid with its shape exactly where it was given
+        let body = engine.get_object(id).unwrap();
+        assert_close(body.pose.position, (2.0, 2.0));
+        let core = body.shape.core(&body.pose);
+        for (actual, expected) in core
+            .vertices
+            .into_iter()
+            .zip(off_center_triangle().vertices)
+        {
+            assert_close(actual, expected);
+        }
+
+        for _ in 0..120 {
+            engine.update(std::time::Duration::from_secs_f32(1.0 / 60.0));
+        }
+        let body = engine.get_object(id).unwrap();
+        assert_close(body.pose.position, (2.0, 2.0));
+        assert_close(
+            polygon_centroid(&body.shape.core(&body.pose).vertices),
+            (2.0, 2.0),
+        );
+    }
+
+    #[test]
+    fn segment_contains_only_points_on_itself() {
+        let segment = Shape::Segment(Segment {
+            start: (0.0, 0.0),
+            end: (1.0, 0.0),
+        });
+        let pose = Pose::default();
+
+        assert!(segment.contains_point(&pose, (0.5, 0.0)));
+        assert!(segment.contains_point(&pose, (1.0, 0.0)));
+        assert!(!segment.contains_point(&pose, (2.0, 0.0)));
+        assert!(!segment.contains_point(&pose, (-5.0, 0.0)));
+        assert!(!segment.contains_point(&pose, (0.5, 0.1)));
+    }
+}
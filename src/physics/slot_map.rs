@@ -0,0 +1,175 @@
+//! Storage with stable generational handles
+
+use serde::{Deserialize, Serialize};
+
+/// A handle to a value in a `SlotMap`
+///
+/// The generation changes every time a slot is reused, so a handle to a removed value never refers to a value inserted later.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+pub struct Key {
+    index: u32,
+    generation: u32,
+}
+
+/// Where a slot's value lives, if anywhere
+#[derive(Clone, Serialize, Deserialize)]
+struct Slot {
+    generation: u32,
+    /// Index into the dense value storage, or `None` if the slot is free
+    dense_index: Option<usize>,
+}
+
+/// A collection handing out stable keys for its values
+///
+/// Values are packed contiguously so they can be iterated and indexed like a slice. Removing a value moves the last value into its place, so dense indices are only stable between removals. Keys stay valid until their own value is removed.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SlotMap<T> {
+    values: Vec<T>,
+    /// The key of the value at each dense index
+    keys: Vec<Key>,
+    slots: Vec<Slot>,
+    free_slots: Vec<u32>,
+}
+
+impl<T> Default for SlotMap<T> {
+    fn default() -> SlotMap<T> {
+        SlotMap::new()
+    }
+}
+
+impl<T> SlotMap<T> {
+    pub fn new() -> SlotMap<T> {
+        SlotMap {
+            values: Vec::new(),
+            keys: Vec::new(),
+            slots: Vec::new(),
+            free_slots: Vec::new(),
+        }
+    }
+
+    /// Insert a value built from its own key
+    pub fn insert_with(&mut self, value: impl FnOnce(Key) -> T) -> Key {
+        let dense_index = self.values.len();
+
+        let key = match self.free_slots.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.dense_index = Some(dense_index);
+                Key {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                self.slots.push(Slot {
+                    generation: 0,
+                    dense_index: Some(dense_index),
+                });
+                Key {
+                    index: (self.slots.len() - 1) as u32,
+                    generation: 0,
+                }
+            }
+        };
+
+        self.values.push(value(key));
+        self.keys.push(key);
+        key
+    }
+
+    /// Remove a value, returning it if the key was still valid
+    pub fn remove(&mut self, key: Key) -> Option<T> {
+        let dense_index = self.dense_index(key)?;
+
+        let slot = &mut self.slots[key.index as usize];
+        slot.dense_index = None;
+        slot.generation = slot.generation.wrapping_add(1);
+        self.free_slots.push(key.index);
+
+        // The last value fills the gap, so its slot needs to point at the new location
+        self.keys.swap_remove(dense_index);
+        if let Some(moved) = self.keys.get(dense_index) {
+            self.slots[moved.index as usize].dense_index = Some(dense_index);
+        }
+
+        Some(self.values.swap_remove(dense_index))
+    }
+
+    /// The current position of a value in the dense storage
+    pub fn dense_index(&self, key: Key) -> Option<usize> {
+        let slot = self.slots.get(key.index as usize)?;
+        if slot.generation == key.generation {
+            slot.dense_index
+        } else {
+            None
+        }
+    }
+
+    pub fn get(&self, key: Key) -> Option<&T> {
+        self.dense_index(key).map(|index| &self.values[index])
+    }
+
+    pub fn get_mut(&mut self, key: Key) -> Option<&mut T> {
+        self.dense_index(key).map(|index| &mut self.values[index])
+    }
+
+    pub fn contains(&self, key: Key) -> bool {
+        self.dense_index(key).is_some()
+    }
+
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    /// All values in dense order
+    pub fn values(&self) -> &[T] {
+        &self.values
+    }
+
+    /// All values in dense order
+    pub fn values_mut(&mut self) -> &mut [T] {
+        &mut self.values
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_key_stays_dead_after_its_slot_is_reused() {
+        let mut map = SlotMap::new();
+        let old = map.insert_with(|_| "old");
+        assert_eq!(map.remove(old), Some("old"));
+
+        let new = map.insert_with(|_| "new");
+        assert_eq!(new.index, old.index, "the free slot should be reused");
+        assert_eq!(map.get(old), None);
+        assert!(!map.contains(old));
+        assert_eq!(map.remove(old), None);
+        assert_eq!(map.get(new), Some(&"new"));
+    }
+
+    #[test]
+    fn removal_moves_the_last_value_and_keeps_its_key() {
+        let mut map = SlotMap::new();
+        let keys: Vec<Key> = (0..4).map(|i| map.insert_with(|_| i)).collect();
+
+        map.remove(keys[1]);
+        assert_eq!(map.values(), &[0, 3, 2]);
+        assert_eq!(map.dense_index(keys[3]), Some(1));
+        for (i, &key) in keys.iter().enumerate().filter(|&(i, _)| i != 1) {
+            assert_eq!(map.get(key), Some(&i));
+        }
+
+        // Removing the last value moves nothing
+        map.remove(keys[2]);
+        assert_eq!(map.values(), &[0, 3]);
+        assert_eq!(map.get(keys[3]), Some(&3));
+        assert_eq!(map.get(keys[0]), Some(&0));
+    }
+}
@@ -0,0 +1,568 @@
+//! Resolving contacts between bodies with sequential impulses
+//!
+//! Every contact point is a constraint that the bodies must not move into each other, plus a friction constraint along the surface. The solver sweeps over all of them several times, each time applying the impulse that fixes one constraint given the current velocities. Fixing one contact disturbs its neighbours, but the sweeps converge so whole stacks settle together rather than one pair at a time.
+//!
+//! Impulses are accumulated per contact point and remembered between updates by the features that produced the point. Starting from last update's impulses, called warm starting, means a resting stack only needs small corrections each update instead of rebuilding its support from nothing.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+
+use super::collision::{FeatureId, Manifold};
+use super::joint::JointConstraint;
+use super::material::{CombineRule, ContactMaterial};
+use super::{inverse_or_zero, pair_mut, vector, Body, BodyId};
+
+/// How overlap between bodies is removed
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PositionCorrection {
+    /// Feed the overlap back into the velocity constraints as an extra separating speed
+    ///
+    /// Simple, but the extra speed stays in the bodies, so deep overlaps make them pop apart. It is not meant for stacking: tall stacks stay up but jitter and creep sideways by as much as half a box over a few hundred updates.
+    Baumgarte,
+
+    /// Push bodies apart with separate pseudo-velocities that move them but are thrown away afterward
+    ///
+    /// Overlap is removed without adding energy, so stacks stay calm.
+    #[default]
+    SplitImpulse,
+}
+
+/// Settings for the contact solver
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SolverSettings {
+    /// Sweeps over the velocity constraints each update. More iterations make stacks stiffer.
+    pub velocity_iterations: u32,
+
+    /// Sweeps over the pseudo-velocities with split impulse position correction. Unused with Baumgarte correction.
+    pub position_iterations: u32,
+
+    /// Whether to start each update from the impulses of the last one
+    ///
+    /// Without it the solver rebuilds every contact impulse from nothing each update, and with the default iterations a stack more than a few bodies tall sinks into itself and collapses. Turn it off only to compare against.
+    pub warm_starting: bool,
+
+    pub position_correction: PositionCorrection,
+
+    /// Fraction of the overlap removed each update, from 0.0 to 1.0
+    pub correction_factor: f32,
+}
+
+impl Default for SolverSettings {
+    fn default() -> SolverSettings {
+        SolverSettings {
+            velocity_iterations: 8,
+            position_iterations: 3,
+            warm_starting: true,
+            position_correction: PositionCorrection::default(),
+            correction_factor: 0.2,
+        }
+    }
+}
+
+/// Overlap allowed before position correction kicks in, which prevents resting bodies from jittering
+const POSITION_CORRECTION_SLOP: f32 = 0.01;
+
+/// Bodies approaching slower than this do not bounce, so resting contacts settle instead of vibrating
+const RESTITUTION_VELOCITY_THRESHOLD: f32 = 0.5;
+
+/// The impulses applied at one contact point in the last update
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedImpulse {
+    id: FeatureId,
+    normal: f32,
+    tangent: f32,
+}
+
+/// The contact impulses between one pair of bodies in the last update
+#[derive(Clone, Serialize, Deserialize)]
+struct CachedManifold {
+    a: BodyId,
+    b: BodyId,
+    impulses: Vec<CachedImpulse>,
+}
+
+/// Contact impulses remembered between updates for warm starting
+///
+/// The cache is part of the engine state, so snapshots include it and a restored simulation continues exactly as it did the first time.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct ContactCache {
+    manifolds: Vec<CachedManifold>,
+}
+
+impl ContactCache {
+    /// The number of body pairs that were touching in the last update
+    pub fn len(&self) -> usize {
+        self.manifolds.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.manifolds.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.manifolds.clear();
+    }
+}
+
+/// One contact point prepared for solving
+struct PointConstraint {
+    id: FeatureId,
+
+    /// Offsets of the contact point from each body position
+    r_a: (f32, f32),
+    r_b: (f32, f32),
+
+    /// The inverse of the effective inverse masses along the normal and tangent
+    normal_mass: f32,
+    tangent_mass: f32,
+
+    penetration: f32,
+
+    /// The separating speed the velocity constraint aims for, from restitution and Baumgarte correction
+    velocity_bias: f32,
+
+    /// Impulses accumulated over all iterations. The pseudo impulse drives split impulse position correction.
+    normal_impulse: f32,
+    tangent_impulse: f32,
+    pseudo_impulse: f32,
+}
+
+/// All contact points between one pair of bodies
+struct ContactConstraint {
+    a: usize,
+    b: usize,
+    normal: (f32, f32),
+    tangent: (f32, f32),
+    material: ContactMaterial,
+    points: Vec<PointConstraint>,
+}
+
+/// Resolve every contact found this update
+///
+/// `contacts` holds the indices of the two bodies of each touching pair with their manifold, with the first index the lower. The cache is read for warm starting and replaced with this update's impulses. Joints are solved in the same sweeps, before the contacts.
+#[allow(clippy::too_many_arguments)]
+pub(super) fn solve(
+    bodies: &mut [Body],
+    contacts: Vec<(usize, usize, Manifold)>,
+    joints: &mut [JointConstraint],
+    cache: &mut ContactCache,
+    settings: &SolverSettings,
+    restitution_rule: CombineRule,
+    friction_rule: CombineRule,
+    dt: f32,
+) {
+    let cached: HashMap<(BodyId, BodyId), &CachedManifold> = cache
+        .manifolds
+        .iter()
+        .map(|manifold| ((manifold.a, manifold.b), manifold))
+        .collect();
+
+    let mut constraints: Vec<ContactConstraint> = contacts
+        .into_iter()
+        .map(|(a, b, manifold)| {
+            let previous = cached.get(&(bodies[a].id, bodies[b].id)).copied();
+            prepare(
+                bodies,
+                a,
+                b,
+                manifold,
+                previous.filter(|_| settings.warm_starting),
+                restitution_rule,
+                friction_rule,
+                settings,
+                dt,
+            )
+        })
+        .collect();
+
+    let velocities_before: Vec<((f32, f32), f32)> = bodies
+        .iter()
+        .map(|body| (body.dynamics.velocity, body.dynamics.angular_velocity))
+        .collect();
+
+    // Warm start by applying the impulses carried over from the last update
+    for joint in joints.iter() {
+        joint.warm_start(bodies);
+    }
+    for constraint in &constraints {
+        let (a, b) = pair_mut(bodies, constraint.a, constraint.b);
+        for point in &constraint.points {
+            let impulse = vector::add(
+                vector::scale(constraint.normal, point.normal_impulse),
+                vector::scale(constraint.tangent, point.tangent_impulse),
+            );
+            apply_impulse(a, b, point, impulse);
+        }
+    }
+
+    for _ in 0..settings.velocity_iterations {
+        for joint in joints.iter_mut() {
+            joint.solve_velocity(bodies);
+        }
+        for constraint in constraints.iter_mut() {
+            solve_velocity(bodies, constraint);
+        }
+    }
+
+    // Contacts are found after the bodies have moved, so the velocity change is also applied to this update's motion, as if the contact had acted from the start of the step. Baumgarte correction leaves this out because its bias speed would then push the bodies apart twice.
+    if settings.position_correction == PositionCorrection::SplitImpulse {
+        for (body, (velocity, angular_velocity)) in bodies.iter_mut().zip(velocities_before) {
+            let velocity_change = vector::sub(body.dynamics.velocity, velocity);
+            body.pose.position =
+                vector::add(body.pose.position, vector::scale(velocity_change, dt));
+            body.pose.orientation += (body.dynamics.angular_velocity - angular_velocity) * dt;
+        }
+
+        correct_positions(bodies, &mut constraints, settings, dt);
+    }
+
+    cache.manifolds = constraints
+        .iter()
+        .map(|constraint| CachedManifold {
+            a: bodies[constraint.a].id,
+            b: bodies[constraint.b].id,
+            impulses: constraint
+                .points
+                .iter()
+                .map(|point| CachedImpulse {
+                    id: point.id,
+                    normal: point.normal_impulse,
+                    tangent: point.tangent_impulse,
+                })
+                .collect(),
+        })
+        .collect();
+}
+
+/// Work out everything about a contact that stays fixed while solving
+#[allow(clippy::too_many_arguments)]
+fn prepare(
+    bodies: &[Body],
+    a: usize,
+    b: usize,
+    manifold: Manifold,
+    previous: Option<&CachedManifold>,
+    restitution_rule: CombineRule,
+    friction_rule: CombineRule,
+    settings: &SolverSettings,
+    dt: f32,
+) -> ContactConstraint {
+    let (body_a, body_b) = (&bodies[a], &bodies[b]);
+    let normal = manifold.normal;
+    let tangent = (normal.1, -normal.0);
+    let material = ContactMaterial::between(
+        &body_a.material,
+        &body_b.material,
+        restitution_rule,
+        friction_rule,
+    );
+
+    let points = manifold
+        .points
+        .into_iter()
+        .map(|contact| {
+            let r_a = vector::sub(contact.point, body_a.pose.position);
+            let r_b = vector::sub(contact.point, body_b.pose.position);
+
+            let relative_velocity = vector::sub(
+                body_b.velocity_at_point(contact.point),
+                body_a.velocity_at_point(contact.point),
+            );
+            let normal_velocity = vector::dot(relative_velocity, normal);
+
+            let mut velocity_bias = 0.0;
+            if normal_velocity < -RESTITUTION_VELOCITY_THRESHOLD {
+                velocity_bias = -material.restitution * normal_velocity;
+            }
+            if settings.position_correction == PositionCorrection::Baumgarte {
+                velocity_bias += settings.correction_factor / dt
+                    * (contact.penetration - POSITION_CORRECTION_SLOP).max(0.0);
+            }
+
+            let carried = previous
+                .and_then(|previous| {
+                    previous
+                        .impulses
+                        .iter()
+                        .find(|impulse| impulse.id == contact.id)
+                })
+                .map_or((0.0, 0.0), |impulse| (impulse.normal, impulse.tangent));
+
+            PointConstraint {
+                id: contact.id,
+                r_a,
+                r_b,
+                normal_mass: inverse_or_zero(effective_inverse_mass(
+                    body_a, body_b, r_a, r_b, normal,
+                )),
+                tangent_mass: inverse_or_zero(effective_inverse_mass(
+                    body_a, body_b, r_a, r_b, tangent,
+                )),
+                penetration: contact.penetration,
+                velocity_bias,
+                normal_impulse: carried.0,
+                tangent_impulse: carried.1,
+                pseudo_impulse: 0.0,
+            }
+        })
+        .collect();
+
+    ContactConstraint {
+        a,
+        b,
+        normal,
+        tangent,
+        material,
+        points,
+    }
+}
+
+/// One sweep over the friction and non-penetration constraints of a pair
+fn solve_velocity(bodies: &mut [Body], constraint: &mut ContactConstraint) {
+    let (a, b) = pair_mut(bodies, constraint.a, constraint.b);
+    let material = &constraint.material;
+
+    // Friction first, so the non-penetration constraints get the last word
+    for point in constraint.points.iter_mut() {
+        let speed = vector::dot(relative_velocity(a, b, point), constraint.tangent);
+        let lambda = -speed * point.tangent_mass;
+
+        // The contact sticks while the total friction impulse stays within the static limit, and otherwise slides against dynamic friction
+        let previous = point.tangent_impulse;
+        let mut total = previous + lambda;
+        if total.abs() > material.static_friction * point.normal_impulse {
+            total = total.signum() * material.dynamic_friction * point.normal_impulse;
+        }
+        point.tangent_impulse = total;
+
+        apply_impulse(
+            a,
+            b,
+            point,
+            vector::scale(constraint.tangent, total - previous),
+        );
+    }
+
+    for point in constraint.points.iter_mut() {
+        let speed = vector::dot(relative_velocity(a, b, point), constraint.normal);
+        let lambda = (point.velocity_bias - speed) * point.normal_mass;
+
+        // Contacts can push but never pull, so the total impulse stays positive
+        let previous = point.normal_impulse;
+        point.normal_impulse = (previous + lambda).max(0.0);
+
+        apply_impulse(
+            a,
+            b,
+            point,
+            vector::scale(constraint.normal, point.normal_impulse - previous),
+        );
+    }
+}
+
+/// Push overlapping bodies apart with pseudo-velocities that are applied to positions and then discarded
+fn correct_positions(
+    bodies: &mut [Body],
+    constraints: &mut [ContactConstraint],
+    settings: &SolverSettings,
+    dt: f32,
+) {
+    // Linear and angular pseudo-velocity of every body
+    let mut pseudo = vec![((0.0, 0.0), 0.0); bodies.len()];
+
+    for _ in 0..settings.position_iterations {
+        for constraint in constraints.iter_mut() {
+            let (a, b) = (&bodies[constraint.a], &bodies[constraint.b]);
+            for point in constraint.points.iter_mut() {
+                let (velocity_a, angular_a) = pseudo[constraint.a];
+                let (velocity_b, angular_b) = pseudo[constraint.b];
+                let relative = vector::sub(
+                    vector::add(velocity_b, vector::cross_scalar(angular_b, point.r_b)),
+                    vector::add(velocity_a, vector::cross_scalar(angular_a, point.r_a)),
+                );
+
+                let target = settings.correction_factor / dt
+                    * (point.penetration - POSITION_CORRECTION_SLOP).max(0.0);
+                let lambda =
+                    (target - vector::dot(relative, constraint.normal)) * point.normal_mass;
+
+                let previous = point.pseudo_impulse;
+                point.pseudo_impulse = (previous + lambda).max(0.0);
+                let impulse = vector::scale(constraint.normal, point.pseudo_impulse - previous);
+
+                pseudo[constraint.a] = (
+                    vector::sub(velocity_a, vector::scale(impulse, a.dynamics.inverse_mass)),
+                    angular_a - a.dynamics.inverse_inertia * vector::cross(point.r_a, impulse),
+                );
+                pseudo[constraint.b] = (
+                    vector::add(velocity_b, vector::scale(impulse, b.dynamics.inverse_mass)),
+                    angular_b + b.dynamics.inverse_inertia * vector::cross(point.r_b, impulse),
+                );
+            }
+        }
+    }
+
+    for (body, (velocity, angular_velocity)) in bodies.iter_mut().zip(pseudo) {
+        body.pose.position = vector::add(body.pose.position, vector::scale(velocity, dt));
+        body.pose.orientation += angular_velocity * dt;
+    }
+}
+
+/// The velocity of the second body relative to the first at a contact point
+fn relative_velocity(a: &Body, b: &Body, point: &PointConstraint) -> (f32, f32) {
+    vector::sub(
+        vector::add(
+            b.dynamics.velocity,
+            vector::cross_scalar(b.dynamics.angular_velocity, point.r_b),
+        ),
+        vector::add(
+            a.dynamics.velocity,
+            vector::cross_scalar(a.dynamics.angular_velocity, point.r_a),
+        ),
+    )
+}
+
+/// Apply an impulse to the second body at a contact point and the opposite impulse to the first
+fn apply_impulse(a: &mut Body, b: &mut Body, point: &PointConstraint, impulse: (f32, f32)) {
+    a.dynamics.velocity = vector::sub(
+        a.dynamics.velocity,
+        vector::scale(impulse, a.dynamics.inverse_mass),
+    );
+    a.dynamics.angular_velocity -= a.dynamics.inverse_inertia * vector::cross(point.r_a, impulse);
+
+    b.dynamics.velocity = vector::add(
+        b.dynamics.velocity,
+        vector::scale(impulse, b.dynamics.inverse_mass),
+    );
+    b.dynamics.angular_velocity += b.dynamics.inverse_inertia * vector::cross(point.r_b, impulse);
+}
+
+/// The inverse of the mass two bodies present to an impulse along `direction` applied at offsets `r_a` and `r_b`
+fn effective_inverse_mass(
+    a: &Body,
+    b: &Body,
+    r_a: (f32, f32),
+    r_b: (f32, f32),
+    direction: (f32, f32),
+) -> f32 {
+    let r_a_cross = vector::cross(r_a, direction);
+    let r_b_cross = vector::cross(r_b, direction);
+
+    a.dynamics.inverse_mass
+        + b.dynamics.inverse_mass
+        + a.dynamics.inverse_inertia * r_a_cross * r_a_cross
+        + b.dynamics.inverse_inertia * r_b_cross * r_b_cross
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::{PhysicsEngine, Rectangle};
+    use super::*;
+
+    /// How well a stack of boxes held together
+    struct StackCheck {
+        /// Furthest any box slid sideways from the center of the stack
+        max_drift: f32,
+        /// How far the top box ended up below where it started
+        sink: f32,
+        /// Speed of the fastest box after the last step, which stays near zero while the stack is at rest
+        final_speed: f32,
+    }
+
+    /// Stack unit boxes on a static ground and let them settle under gravity for a number of fixed 60 Hz steps
+    ///
+    /// A solver that converges poorly lets the stack sink into itself, jitter or topple, which shows as drift and speed.
+    fn box_stack(settings: SolverSettings, boxes: usize, steps: u32) -> StackCheck {
+        let mut engine = PhysicsEngine::new();
+        engine.solver = settings;
+        engine.gravity = (0.0, -9.81);
+
+        let ground = engine.add_object(Rectangle {
+            origin: (0.0, 0.0),
+            half_extents: (20.0, 0.5),
+        });
+        ground.dynamics.set_static();
+        ground.set_position((0.0, -0.5));
+
+        let mut ids = Vec::new();
+        for level in 0..boxes {
+            let body = engine.add_object(Rectangle {
+                origin: (0.0, 0.0),
+                half_extents: (0.5, 0.5),
+            });
+            body.set_position((0.0, 0.5 + level as f32));
+            ids.push(body.id);
+        }
+
+        let mut max_drift: f32 = 0.0;
+        for _ in 0..steps {
+            engine.update(Duration::from_secs_f32(1.0 / 60.0));
+            for id in &ids {
+                let position = engine.get_object(*id).unwrap().pose.position;
+                max_drift = max_drift.max(position.0.abs());
+            }
+        }
+
+        let sink = ids.last().map_or(0.0, |id| {
+            let position = engine.get_object(*id).unwrap().pose.position;
+            boxes as f32 - 0.5 - position.1
+        });
+        let final_speed = ids
+            .iter()
+            .map(|id| vector::length(engine.get_object(*id).unwrap().dynamics.velocity))
+            .fold(0.0, f32::max);
+
+        StackCheck {
+            max_drift,
+            sink,
+            final_speed,
+        }
+    }
+
+    #[test]
+    fn ten_box_stack_stays_standing() {
+        let check = box_stack(SolverSettings::default(), 10, 1000);
+        assert!(check.max_drift < 0.15, "drift {}", check.max_drift);
+        assert!(check.sink < 0.15, "sink {}", check.sink);
+        assert!(check.final_speed < 0.1, "speed {}", check.final_speed);
+    }
+
+    #[test]
+    fn warm_starting_keeps_a_stack_from_collapsing() {
+        let warm = box_stack(SolverSettings::default(), 10, 1000);
+        let cold = box_stack(
+            SolverSettings {
+                warm_starting: false,
+                ..SolverSettings::default()
+            },
+            10,
+            1000,
+        );
+
+        // Without warm starting the stack sinks into itself and falls over
+        assert!(cold.sink > 1.0, "cold start sink {}", cold.sink);
+        assert!(cold.max_drift > 1.0, "cold start drift {}", cold.max_drift);
+        assert!(cold.sink > 5.0 * warm.sink);
+        assert!(cold.max_drift > 5.0 * warm.max_drift);
+    }
+
+    #[test]
+    fn baumgarte_stack_stands_but_creeps() {
+        let check = box_stack(
+            SolverSettings {
+                position_correction: PositionCorrection::Baumgarte,
+                ..SolverSettings::default()
+            },
+            10,
+            1000,
+        );
+
+        // Up to the drift documented on `PositionCorrection::Baumgarte`, but no more
+        assert!(check.sink < 0.15, "sink {}", check.sink);
+        assert!(check.max_drift < 0.75, "drift {}", check.max_drift);
+    }
+}
@@ -0,0 +1,446 @@
+//! Bodies linked by springs into ropes, cloth and soft bodies
+//!
+//! A spring network is a set of Hooke springs between bodies, usually small circles acting as the nodes of a soft object. Unlike distance joints, the springs are plain forces evaluated along with gravity and force fields, which makes them cheap for large networks but limits how stiff they can be. A node of mass m pulled by springs of total stiffness k stays stable while k / m is below about (2 / dt)², roughly 14000 at 60 updates per second.
+
+use serde::{Deserialize, Serialize};
+
+use super::integrator::State;
+use super::joint::Spring;
+use super::slot_map::Key;
+use super::{vector, BodyId, PhysicsEngine};
+
+/// A unique identifier for a spring network in the physics engine
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SpringNetworkId(pub(super) Key);
+
+/// A spring pulling two bodies toward a rest distance apart
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Link {
+    pub a: BodyId,
+    pub b: BodyId,
+    pub rest_length: f32,
+    pub spring: Spring,
+}
+
+/// Gas filling the polygon outlined by a loop of bodies
+///
+/// The gas is at the pressure of its surroundings while the polygon has its rest area and follows the ideal gas law otherwise, so it pushes outward when squashed and pulls inward when stretched.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Pressure {
+    /// The loop of bodies in counter-clockwise order
+    pub outline: Vec<BodyId>,
+    pub rest_area: f32,
+    /// The pressure difference with the surroundings once squashed to half the rest area, as force per unit of outline length
+    pub stiffness: f32,
+}
+
+impl Pressure {
+    /// Gas filling a loop of bodies, at rest at the area the loop has now
+    ///
+    /// The loop can go either way around. A clockwise loop is reversed, since the gas would otherwise push the outline inward.
+    pub fn around(engine: &PhysicsEngine, outline: &[BodyId], stiffness: f32) -> Pressure {
+        let positions: Vec<(f32, f32)> = outline
+            .iter()
+            .filter_map(|&id| engine.get_object(id))
+            .map(|body| body.pose.position)
+            .collect();
+
+        let mut outline = outline.to_vec();
+        let area = polygon_area(&positions);
+        if area < 0.0 {
+            outline.reverse();
+        }
+
+        Pressure {
+            outline,
+            rest_area: area.abs(),
+            stiffness,
+        }
+    }
+}
+
+/// Squashing the outline further than this fraction of its rest area does not raise the pressure any more, which keeps a collapsed blob from exploding
+const MIN_AREA_FRACTION: f32 = 0.1;
+
+/// Bodies linked by springs
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct SpringNetwork {
+    pub links: Vec<Link>,
+    #[serde(default)]
+    pub pressure: Option<Pressure>,
+}
+
+impl SpringNetwork {
+    pub fn new() -> SpringNetwork {
+        SpringNetwork::default()
+    }
+
+    /// Link two bodies with a spring at rest at their current distance
+    ///
+    /// Nothing is linked if either body does not exist.
+    pub fn link(&mut self, engine: &PhysicsEngine, a: BodyId, b: BodyId, spring: Spring) {
+        let (Some(body_a), Some(body_b)) = (engine.get_object(a), engine.get_object(b)) else {
+            return;
+        };
+
+        self.links.push(Link {
+            a,
+            b,
+            rest_length: vector::length(vector::sub(body_b.pose.position, body_a.pose.position)),
+            spring,
+        });
+    }
+
+    /// Every body in the network once, in the order it was first linked
+    pub fn bodies(&self) -> Vec<BodyId> {
+        let mut bodies = Vec::new();
+        let linked = self.links.iter().flat_map(|link| [link.a, link.b]);
+        let outline = self
+            .pressure
+            .iter()
+            .flat_map(|pressure| pressure.outline.iter().copied());
+        for body in linked.chain(outline) {
+            if !bodies.contains(&body) {
+                bodies.push(body);
+            }
+        }
+        bodies
+    }
+
+    /// Link each node to the next, so the nodes hang and swing like a rope
+    pub fn rope(engine: &PhysicsEngine, nodes: &[BodyId], spring: Spring) -> SpringNetwork {
+        let mut network = SpringNetwork::new();
+        for pair in nodes.windows(2) {
+            network.link(engine, pair[0], pair[1], spring);
+        }
+        network
+    }
+
+    /// Link a grid of nodes, given row by row, to their neighbours across, down and diagonally, like woven cloth
+    ///
+    /// The diagonal links keep the weave from shearing flat, while nothing resists folding, so the cloth drapes.
+    pub fn cloth(
+        engine: &PhysicsEngine,
+        nodes: &[BodyId],
+        columns: usize,
+        spring: Spring,
+    ) -> SpringNetwork {
+        let mut network = SpringNetwork::new();
+        if columns == 0 {
+            return network;
+        }
+
+        let rows = nodes.len() / columns;
+        let node = |row: usize, column: usize| nodes[row * columns + column];
+        for row in 0..rows {
+            for column in 0..columns {
+                let right = column + 1 < columns;
+                let down = row + 1 < rows;
+                if right {
+                    network.link(engine, node(row, column), node(row, column + 1), spring);
+                }
+                if down {
+                    network.link(engine, node(row, column), node(row + 1, column), spring);
+                }
+                if right && down {
+                    network.link(engine, node(row, column), node(row + 1, column + 1), spring);
+                    network.link(engine, node(row, column + 1), node(row + 1, column), spring);
+                }
+            }
+        }
+        network
+    }
+
+    /// Link a loop of nodes into a skin and fill it with gas, making a soft blob
+    ///
+    /// The gas is at rest at the area the outline has now, whichever way the loop goes around.
+    pub fn blob(
+        engine: &PhysicsEngine,
+        outline: &[BodyId],
+        spring: Spring,
+        pressure: f32,
+    ) -> SpringNetwork {
+        let mut network = SpringNetwork::new();
+        for (i, &node) in outline.iter().enumerate() {
+            network.link(engine, node, outline[(i + 1) % outline.len()], spring);
+        }
+        network.pressure = Some(Pressure::around(engine, outline, pressure));
+        network
+    }
+
+    /// Drop every link to a body, and the gas if the body is part of its outline
+    pub(super) fn remove_body(&mut self, id: BodyId) {
+        self.links.retain(|link| link.a != id && link.b != id);
+        if let Some(pressure) = &self.pressure {
+            if pressure.outline.contains(&id) {
+                self.pressure = None;
+            }
+        }
+    }
+
+    /// Replace body IDs with positions in the engine's storage for evaluating forces
+    ///
+    /// Links to bodies that no longer exist are left out, as is the gas if its outline is broken.
+    pub(super) fn resolve(&self, index: impl Fn(BodyId) -> Option<usize>) -> ResolvedNetwork {
+        let links = self
+            .links
+            .iter()
+            .filter_map(|link| {
+                Some(ResolvedLink {
+                    a: index(link.a)?,
+                    b: index(link.b)?,
+                    rest_length: link.rest_length,
+                    spring: link.spring,
+                })
+            })
+            .collect();
+
+        let pressure = self.pressure.as_ref().and_then(|pressure| {
+            Some(ResolvedPressure {
+                outline: pressure
+                    .outline
+                    .iter()
+                    .map(|&id| index(id))
+                    .collect::<Option<Vec<usize>>>()?,
+                rest_area: pressure.rest_area,
+                stiffness: pressure.stiffness,
+            })
+        });
+
+        ResolvedNetwork { links, pressure }
+    }
+}
+
+struct ResolvedLink {
+    a: usize,
+    b: usize,
+    rest_length: f32,
+    spring: Spring,
+}
+
+struct ResolvedPressure {
+    outline: Vec<usize>,
+    rest_area: f32,
+    stiffness: f32,
+}
+
+/// A spring network referring to bodies by their position in the engine's storage
+pub(super) struct ResolvedNetwork {
+    links: Vec<ResolvedLink>,
+    pressure: Option<ResolvedPressure>,
+}
+
+impl ResolvedNetwork {
+    /// Add the spring and gas forces on each body at the given states
+    pub fn accumulate_forces(&self, states: &[State], forces: &mut [(f32, f32)]) {
+        for link in &self.links {
+            let (a, b) = (&states[link.a], &states[link.b]);
+            let offset = vector::sub(b.position, a.position);
+            let length = vector::length(offset);
+            if length <= f32::EPSILON {
+                continue;
+            }
+            let direction = vector::scale(offset, 1.0 / length);
+
+            // Positive tension pulls the bodies together
+            let stretch_speed = vector::dot(vector::sub(b.velocity, a.velocity), direction);
+            let tension = link.spring.stiffness * (length - link.rest_length)
+                + link.spring.damping * stretch_speed;
+            let force = vector::scale(direction, tension);
+
+            forces[link.a] = vector::add(forces[link.a], force);
+            forces[link.b] = vector::sub(forces[link.b], force);
+        }
+
+        if let Some(pressure) = &self.pressure {
+            let positions: Vec<(f32, f32)> = pressure
+                .outline
+                .iter()
+                .map(|&i| states[i].position)
+                .collect();
+            let area = polygon_area(&positions).max(pressure.rest_area * MIN_AREA_FRACTION);
+            if area <= 0.0 {
+                return;
+            }
+
+            // The gas pushes each edge outward in proportion to its length, shared between its ends
+            let gauge_pressure = pressure.stiffness * (pressure.rest_area / area - 1.0);
+            for (i, &start) in pressure.outline.iter().enumerate() {
+                let end = pressure.outline[(i + 1) % pressure.outline.len()];
+                let edge = vector::sub(states[end].position, states[start].position);
+                let force = vector::scale((edge.1, -edge.0), gauge_pressure / 2.0);
+
+                forces[start] = vector::add(forces[start], force);
+                forces[end] = vector::add(forces[end], force);
+            }
+        }
+    }
+}
+
+/// The area enclosed by a polygon, positive when its points run counter-clockwise
+fn polygon_area(points: &[(f32, f32)]) -> f32 {
+    let doubled: f32 = (0..points.len())
+        .map(|i| vector::cross(points[i], points[(i + 1) % points.len()]))
+        .sum();
+    doubled / 2.0
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::super::Circle;
+    use super::*;
+
+    /// Small circles at the given positions
+    fn nodes(engine: &mut PhysicsEngine, positions: &[(f32, f32)]) -> Vec<BodyId> {
+        positions
+            .iter()
+            .map(|&position| {
+                let node = engine.add_object(Circle {
+                    origin: (0.0, 0.0),
+                    radius: 0.1,
+                });
+                node.set_position(position);
+                node.id
+            })
+            .collect()
+    }
+
+    /// The gas forces on each node of the outline once the nodes are moved to `positions`
+    fn gas_forces(
+        engine: &PhysicsEngine,
+        pressure: &Pressure,
+        positions: &[(f32, f32)],
+    ) -> Vec<(f32, f32)> {
+        let network = SpringNetwork {
+            links: Vec::new(),
+            pressure: Some(pressure.clone()),
+        };
+        let resolved = network.resolve(|id| engine.objects.dense_index(id.0));
+        let mut states: Vec<State> = engine
+            .objects
+            .values()
+            .iter()
+            .map(|body| State {
+                position: body.pose.position,
+                velocity: (0.0, 0.0),
+                orientation: 0.0,
+                angular_velocity: 0.0,
+            })
+            .collect();
+        for (&id, &position) in pressure.outline.iter().zip(positions) {
+            states[engine.objects.dense_index(id.0).unwrap()].position = position;
+        }
+
+        let mut forces = vec![(0.0, 0.0); states.len()];
+        resolved.accumulate_forces(&states, &mut forces);
+        pressure
+            .outline
+            .iter()
+            .map(|&id| forces[engine.objects.dense_index(id.0).unwrap()])
+            .collect()
+    }
+
+    /// Check the gas around a square of side 2 centered on the origin pushes out when the square is squashed and pulls in when it is stretched
+    fn assert_gas_pushes_outward(engine: &PhysicsEngine, pressure: &Pressure) {
+        assert!(
+            (pressure.rest_area - 4.0).abs() < 1e-5,
+            "rest area {}",
+            pressure.rest_area
+        );
+
+        let positions: Vec<(f32, f32)> = pressure
+            .outline
+            .iter()
+            .map(|&id| engine.get_object(id).unwrap().pose.position)
+            .collect();
+        for (scale, outward) in [(0.8, true), (1.2, false)] {
+            let moved: Vec<(f32, f32)> = positions
+                .iter()
+                .map(|&position| vector::scale(position, scale))
+                .collect();
+            for (force, position) in gas_forces(engine, pressure, &moved).into_iter().zip(moved) {
+                let push = vector::dot(force, position);
+                assert!(
+                    (push > 0.0) == outward,
+                    "the gas pushes {force:?} at {position:?} scaled by {scale}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn squashed_blob_pushes_outward() {
+        let mut engine = PhysicsEngine::new();
+        let square = [(1.0, -1.0), (1.0, 1.0), (-1.0, 1.0), (-1.0, -1.0)];
+        let outline = nodes(&mut engine, &square);
+        let pressure = Pressure::around(&engine, &outline, 10.0);
+        assert!(pressure.outline == outline);
+        assert_gas_pushes_outward(&engine, &pressure);
+
+        // At rest the gas does nothing
+        for force in gas_forces(&engine, &pressure, &square) {
+            assert!(vector::length(force) < 1e-5, "the gas pushes {force:?}");
+        }
+    }
+
+    #[test]
+    fn clockwise_outline_is_reversed() {
+        let mut engine = PhysicsEngine::new();
+        let square = [(-1.0, -1.0), (-1.0, 1.0), (1.0, 1.0), (1.0, -1.0)];
+        let outline = nodes(&mut engine, &square);
+        let pressure = Pressure::around(&engine, &outline, 10.0);
+
+        let reversed: Vec<BodyId> = outline.iter().rev().copied().collect();
+        assert!(pressure.outline == reversed);
+        assert_gas_pushes_outward(&engine, &pressure);
+
+        let spring = Spring {
+            stiffness: 10.0,
+            damping: 0.0,
+        };
+        let blob = SpringNetwork::blob(&engine, &outline, spring, 10.0);
+        assert!(blob.pressure.unwrap().rest_area > 0.0);
+    }
+
+    #[test]
+    fn rope_hangs_near_its_rest_length() {
+        let mut engine = PhysicsEngine::new();
+        engine.gravity = (0.0, -10.0);
+        let positions: Vec<(f32, f32)> = (0..6).map(|i| (0.0, -0.5 * i as f32)).collect();
+        let rope = nodes(&mut engine, &positions);
+        engine
+            .get_object_mut(rope[0])
+            .unwrap()
+            .dynamics
+            .set_static();
+
+        // Stiff enough to stretch only a little, and damped enough to settle, for the node mass
+        let mass = engine.get_object(rope[1]).unwrap().dynamics.mass();
+        let spring = Spring {
+            stiffness: 2000.0 * mass,
+            damping: 10.0 * mass,
+        };
+        let network = SpringNetwork::rope(&engine, &rope, spring);
+        engine.add_spring_network(network);
+
+        for _ in 0..600 {
+            engine.update(Duration::from_secs_f32(1.0 / 60.0));
+        }
+
+        // Each link stretches under the weight of the nodes below it
+        for (i, pair) in rope.windows(2).enumerate() {
+            let a = engine.get_object(pair[0]).unwrap().pose.position;
+            let b = engine.get_object(pair[1]).unwrap().pose.position;
+            let length = vector::length(vector::sub(b, a));
+            let below = (rope.len() - 1 - i) as f32;
+            let expected = 0.5 + below * mass * 10.0 / spring.stiffness;
+            assert!(
+                (length - expected).abs() < 1e-3,
+                "link {i} is {length} long, expected {expected}"
+            );
+            assert!(b.0.abs() < 1e-3, "node {} swung to {b:?}", i + 1);
+        }
+    }
+}
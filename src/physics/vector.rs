@@ -0,0 +1,53 @@
+//! Helpers for 2D vector math on `(f32, f32)` tuples
+
+pub fn add(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    (a.0 + b.0, a.1 + b.1)
+}
+
+pub fn sub(a: (f32, f32), b: (f32, f32)) -> (f32, f32) {
+    (a.0 - b.0, a.1 - b.1)
+}
+
+pub fn scale(a: (f32, f32), s: f32) -> (f32, f32) {
+    (a.0 * s, a.1 * s)
+}
+
+pub fn dot(a: (f32, f32), b: (f32, f32)) -> f32 {
+    a.0 * b.0 + a.1 * b.1
+}
+
+pub fn length_squared(a: (f32, f32)) -> f32 {
+    dot(a, a)
+}
+
+pub fn length(a: (f32, f32)) -> f32 {
+    length_squared(a).sqrt()
+}
+
+/// The z component of the 3D cross product of two vectors in the plane
+pub fn cross(a: (f32, f32), b: (f32, f32)) -> f32 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+/// The cross product of a scalar (a vector along the z axis) with a vector in the plane
+///
+/// For an angular velocity and an offset from the center of rotation, this is the linear velocity of the offset point.
+pub fn cross_scalar(s: f32, a: (f32, f32)) -> (f32, f32) {
+    (-s * a.1, s * a.0)
+}
+
+/// Scale a vector to unit length. The zero vector is returned unchanged.
+pub fn normalize(a: (f32, f32)) -> (f32, f32) {
+    let length = length(a);
+    if length > f32::EPSILON {
+        scale(a, 1.0 / length)
+    } else {
+        a
+    }
+}
+
+/// Rotate a vector counter-clockwise by an angle in radians
+pub fn rotate(a: (f32, f32), angle: f32) -> (f32, f32) {
+    let (sin, cos) = angle.sin_cos();
+    (a.0 * cos - a.1 * sin, a.0 * sin + a.1 * cos)
+}
@@ -5,21 +5,63 @@ extern crate skia_safe;
 
 use gl::types::*;
 use skia_safe::gpu::{gl as skia_gl, DirectContext, RecordingContext};
-use skia_safe::{gpu, Surface};
+use skia_safe::{gpu, Color, Surface};
 
 use crate::model::{primitive::*, Primitive};
 use crate::physics::Pose;
 
+pub mod raster;
+pub mod svg;
+
+pub use raster::RasterRenderer;
+pub use svg::SvgRenderer;
+
 /// A renderer that can draw 2D models
 ///
 /// Renderers draw objects in a region of the physics simulation to a surface. All objects models consist of a set of primitive shapes.
 pub trait Renderer {
     /// Selects the region of the physics simulation to draw and fit it to the surface
+    ///
+    /// How the region is fit depends on the view fit mode. The region actually shown may be larger or smaller than requested but always has the same center.
     fn set_physics_region(&mut self, p1: (f32, f32), p2: (f32, f32));
 
+    /// The region of the physics simulation currently shown on the surface, after fitting
     fn get_physics_view_region(&self) -> ((f32, f32), (f32, f32));
 
-    /// Resize the surface
+    /// Choose how the physics region is fit to a surface with different proportions, refitting the current region
+    fn set_view_fit(&mut self, fit: ViewFit);
+
+    fn get_view_fit(&self) -> ViewFit;
+
+    /// The size of the surface in pixels
+    fn get_surface_dimensions(&self) -> (i32, i32);
+
+    /// Convert a point on the surface in pixels to physics coordinates
+    ///
+    /// Surface coordinates start at the top-left corner with the y-axis pointing down, while the physics y-axis points up. The fitted view region fills the surface exactly, so the x and y scales only differ with `ViewFit::Stretch`.
+    fn screen_to_physics(&self, point: (f32, f32)) -> (f32, f32) {
+        let (p1, p2) = self.get_physics_view_region();
+        let (width, height) = self.get_surface_dimensions();
+
+        // Fractions of the way across the surface, measured from the bottom-left corner
+        let u = point.0 / width as f32;
+        let v = 1.0 - point.1 / height as f32;
+
+        (p1.0 + u * (p2.0 - p1.0), p1.1 + v * (p2.1 - p1.1))
+    }
+
+    /// Convert a point in physics coordinates to pixels on the surface. The inverse of `screen_to_physics`.
+    fn physics_to_screen(&self, point: (f32, f32)) -> (f32, f32) {
+        let (p1, p2) = self.get_physics_view_region();
+        let (width, height) = self.get_surface_dimensions();
+
+        let u = (point.0 - p1.0) / (p2.0 - p1.0);
+        let v = (point.1 - p1.1) / (p2.1 - p1.1);
+
+        (u * width as f32, (1.0 - v) * height as f32)
+    }
+
+    /// Resize the surface, refitting the last requested physics region to it
     fn resize_surface(&mut self, dimensions: (i32, i32));
 
     /// Prepare to draw a new frame
@@ -45,6 +87,128 @@ pub trait Renderer {
 
     /// Primitive shape
     fn draw_rectangle(&mut self, rectangle: &Rectangle, pose: &Pose);
+
+    /// Draw a straight line between two points, with a width in physics units
+    ///
+    /// Lines are meant for debugging overlays like world bounds rather than as models.
+    fn draw_line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: Color);
+}
+
+/// How a physics region is fit to a surface with different proportions
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ViewFit {
+    /// Stretch the region to exactly fill the surface. Shapes are distorted when the proportions differ.
+    Stretch,
+    /// Show the whole region as large as possible, revealing more of the simulation along the surface's longer side
+    #[default]
+    Letterbox,
+    /// Match the width of the region, showing more or less of it vertically
+    FitWidth,
+    /// Cover the whole surface with the region, cropping it along one axis
+    Fill,
+}
+
+impl ViewFit {
+    /// The region shown on a surface when the region from `p1` to `p2` is requested
+    ///
+    /// Every mode except `Stretch` gives a region with the same proportions as the surface, so physics units are square on screen.
+    pub fn fit(
+        self,
+        p1: (f32, f32),
+        p2: (f32, f32),
+        surface_dimensions: (i32, i32),
+    ) -> ((f32, f32), (f32, f32)) {
+        let (width, height) = surface_dimensions;
+        if self == ViewFit::Stretch || width <= 0 || height <= 0 {
+            return (p1, p2);
+        }
+
+        let size = (p2.0 - p1.0, p2.1 - p1.1);
+        let surface_aspect = width as f32 / height as f32;
+        let surface_is_wider = surface_aspect > size.0 / size.1;
+
+        // Keep one side of the region and extend or crop the other to match the surface
+        let keep_width = match self {
+            ViewFit::Stretch => unreachable!(),
+            ViewFit::Letterbox => !surface_is_wider,
+            ViewFit::FitWidth => true,
+            ViewFit::Fill => surface_is_wider,
+        };
+        let fitted_size = if keep_width {
+            (size.0, size.0 / surface_aspect)
+        } else {
+            (size.1 * surface_aspect, size.1)
+        };
+
+        let center = ((p1.0 + p2.0) / 2.0, (p1.1 + p2.1) / 2.0);
+        let half = (fitted_size.0 / 2.0, fitted_size.1 / 2.0);
+        (
+            (center.0 - half.0, center.1 - half.1),
+            (center.0 + half.0, center.1 + half.1),
+        )
+    }
+
+    /// The next mode, for cycling through them
+    pub fn next(self) -> ViewFit {
+        match self {
+            ViewFit::Stretch => ViewFit::Letterbox,
+            ViewFit::Letterbox => ViewFit::FitWidth,
+            ViewFit::FitWidth => ViewFit::Fill,
+            ViewFit::Fill => ViewFit::Stretch,
+        }
+    }
+}
+
+/// A requested physics region and the region actually shown after fitting it to a surface
+///
+/// The requested region is kept so it can be refit whenever the fit mode or surface changes, without the shown region drifting away from what was asked for.
+#[derive(Clone, Copy)]
+pub(crate) struct FittedView {
+    requested: ((f32, f32), (f32, f32)),
+    shown: ((f32, f32), (f32, f32)),
+    fit: ViewFit,
+}
+
+impl FittedView {
+    /// Show one physics unit per pixel, with the physics origin at the bottom-left corner of the surface
+    pub fn new(surface_dimensions: (i32, i32)) -> FittedView {
+        let region = (
+            (0.0, 0.0),
+            (surface_dimensions.0 as f32, surface_dimensions.1 as f32),
+        );
+        FittedView {
+            requested: region,
+            shown: region,
+            fit: ViewFit::default(),
+        }
+    }
+
+    /// Ask for the region from `p1` to `p2` and fit it to the surface
+    pub fn request(&mut self, p1: (f32, f32), p2: (f32, f32), surface_dimensions: (i32, i32)) {
+        self.requested = (p1, p2);
+        self.refit(surface_dimensions);
+    }
+
+    /// Change the fit mode and refit the requested region
+    pub fn set_fit(&mut self, fit: ViewFit, surface_dimensions: (i32, i32)) {
+        self.fit = fit;
+        self.refit(surface_dimensions);
+    }
+
+    /// Fit the requested region to a surface again, such as after it is resized
+    pub fn refit(&mut self, surface_dimensions: (i32, i32)) {
+        let (p1, p2) = self.requested;
+        self.shown = self.fit.fit(p1, p2, surface_dimensions);
+    }
+
+    /// The region shown on the surface
+    pub fn shown(&self) -> ((f32, f32), (f32, f32)) {
+        self.shown
+    }
+
+    pub fn fit(&self) -> ViewFit {
+        self.fit
+    }
 }
 
 /// Properties of a GL surface
@@ -62,41 +226,39 @@ pub struct SkiaRenderer {
 
     surface_properties: SurfaceProperties,
 
-    view_region: ((f32, f32), (f32, f32)),
+    view: FittedView,
 }
 
 impl Renderer for SkiaRenderer {
     fn set_physics_region(&mut self, p1: (f32, f32), p2: (f32, f32)) {
-        // Get the surface dimensions as f32
-        let surface_width_f = self.surface.width() as f32;
-        let surface_height_f = self.surface.height() as f32;
-
-        let canvas = self.surface.canvas();
-        canvas.reset_matrix();
-
-        // Flip the y-axis to match the physics coordinate system
-        canvas.scale((1.0, -1.0));
-        canvas.translate((0.0, -surface_height_f));
+        self.view.request(p1, p2, self.get_surface_dimensions());
+        apply_physics_region(&mut self.surface, self.view.shown());
+    }
 
-        // Scale the desired region to the surface dimensions
-        canvas.scale((
-            surface_width_f / (p2.0 - p1.0),
-            surface_height_f / (p2.1 - p1.1),
-        ));
+    fn get_physics_view_region(&self) -> ((f32, f32), (f32, f32)) {
+        self.view.shown()
+    }
 
-        // Translate the canvas to use the origin of the physics region
-        canvas.translate((-p1.0, -p1.1));
+    fn get_surface_dimensions(&self) -> (i32, i32) {
+        (self.surface.width(), self.surface.height())
+    }
 
-        self.view_region = (p1, p2);
+    fn set_view_fit(&mut self, fit: ViewFit) {
+        self.view.set_fit(fit, self.get_surface_dimensions());
+        apply_physics_region(&mut self.surface, self.view.shown());
     }
 
-    fn get_physics_view_region(&self) -> ((f32, f32), (f32, f32)) {
-        self.view_region
+    fn get_view_fit(&self) -> ViewFit {
+        self.view.fit()
     }
 
     fn resize_surface(&mut self, dimensions: (i32, i32)) {
         self.surface_properties.dimensions = dimensions;
         self.surface = Self::create_surface(&mut self.context, &self.surface_properties);
+
+        // A new surface starts with an identity matrix
+        self.view.refit(self.get_surface_dimensions());
+        apply_physics_region(&mut self.surface, self.view.shown());
     }
 
     fn begin_new_frame(&mut self) {
@@ -108,34 +270,90 @@ impl Renderer for SkiaRenderer {
     }
 
     fn draw_circle(&mut self, circle: &Circle, pose: &Pose) {
-        let canvas = self.surface.canvas();
-        let mut paint = skia_safe::Paint::default();
-        paint.set_color(circle.color);
-
-        canvas.draw_circle(
-            (
-                circle.origin.0 + pose.position.0,
-                circle.origin.1 + pose.position.1,
-            ),
-            circle.radius,
-            &paint,
-        );
+        draw_circle(self.surface.canvas(), circle, pose);
     }
 
     fn draw_rectangle(&mut self, rectangle: &Rectangle, pose: &Pose) {
-        let canvas = self.surface.canvas();
-        let mut paint = skia_safe::Paint::default();
-        paint.set_color(rectangle.color);
-        canvas.draw_rect(
-            skia_safe::Rect::from_xywh(
-                rectangle.origin.0 + pose.position.0,
-                rectangle.origin.1 + pose.position.1,
-                rectangle.dimensions.0,
-                rectangle.dimensions.1,
-            ),
-            &paint,
-        );
+        draw_rectangle(self.surface.canvas(), rectangle, pose);
     }
+
+    fn draw_line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: Color) {
+        draw_line(self.surface.canvas(), from, to, width, color);
+    }
+}
+
+/// Set the canvas matrix of a surface so the region from `p1` to `p2` of the physics simulation fills it
+fn apply_physics_region(surface: &mut Surface, (p1, p2): ((f32, f32), (f32, f32))) {
+    // Get the surface dimensions as f32
+    let surface_width_f = surface.width() as f32;
+    let surface_height_f = surface.height() as f32;
+
+    let canvas = surface.canvas();
+    canvas.reset_matrix();
+
+    // Flip the y-axis to match the physics coordinate system
+    canvas.scale((1.0, -1.0));
+    canvas.translate((0.0, -surface_height_f));
+
+    // Scale the desired region to the surface dimensions
+    canvas.scale((
+        surface_width_f / (p2.0 - p1.0),
+        surface_height_f / (p2.1 - p1.1),
+    ));
+
+    // Translate the canvas to use the origin of the physics region
+    canvas.translate((-p1.0, -p1.1));
+}
+
+fn draw_circle(canvas: &skia_safe::Canvas, circle: &Circle, pose: &Pose) {
+    let mut paint = skia_safe::Paint::default();
+    paint.set_color(circle.color);
+
+    canvas.save();
+    apply_pose(canvas, pose);
+    canvas.draw_circle(circle.origin, circle.radius, &paint);
+    canvas.restore();
+}
+
+fn draw_rectangle(canvas: &skia_safe::Canvas, rectangle: &Rectangle, pose: &Pose) {
+    let mut paint = skia_safe::Paint::default();
+    paint.set_color(rectangle.color);
+
+    canvas.save();
+    apply_pose(canvas, pose);
+    canvas.draw_rect(
+        skia_safe::Rect::from_xywh(
+            rectangle.origin.0,
+            rectangle.origin.1,
+            rectangle.dimensions.0,
+            rectangle.dimensions.1,
+        ),
+        &paint,
+    );
+    canvas.restore();
+}
+
+fn draw_line(
+    canvas: &skia_safe::Canvas,
+    from: (f32, f32),
+    to: (f32, f32),
+    width: f32,
+    color: Color,
+) {
+    let mut paint = skia_safe::Paint::default();
+    paint.set_color(color);
+    paint.set_style(skia_safe::PaintStyle::Stroke);
+    paint.set_stroke_width(width);
+
+    canvas.draw_line(from, to, &paint);
+}
+
+/// Move the canvas into the local frame of a pose so models can be drawn relative to the body position
+///
+/// Models rotate about the body position. The y-axis is already flipped, so a positive orientation turns counter-clockwise on screen just like in the physics coordinate system.
+fn apply_pose(canvas: &skia_safe::Canvas, pose: &Pose) {
+    canvas.translate(pose.position);
+    canvas.rotate(pose.orientation.to_degrees(), None);
 }
 
 impl SkiaRenderer {
@@ -143,19 +361,16 @@ impl SkiaRenderer {
         let interface = skia_gl::Interface::new_native().unwrap();
         let mut context = DirectContext::new_gl(Some(interface), None).unwrap();
 
-        let surface = Self::create_surface(&mut context, properties);
-        let surface_dims = (surface.width() as f32, surface.height() as f32);
+        let mut surface = Self::create_surface(&mut context, properties);
+        let view = FittedView::new((surface.width(), surface.height()));
+        apply_physics_region(&mut surface, view.shown());
 
-        let mut new_renderer = SkiaRenderer {
+        SkiaRenderer {
             context,
             surface,
             surface_properties: *properties,
-            view_region: ((0.0, 0.0), (0.0, 0.0)),
-        };
-
-        new_renderer.set_physics_region((0.0, 0.0), surface_dims);
-
-        new_renderer
+            view,
+        }
     }
 
     /// Create a new surface
@@ -192,3 +407,62 @@ impl SkiaRenderer {
         .unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: (f32, f32), expected: (f32, f32)) {
+        assert!(
+            (actual.0 - expected.0).abs() < 1e-3 && (actual.1 - expected.1).abs() < 1e-3,
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    /// An SVG renderer of the given size stretched over a region
+    fn stretched(dimensions: (i32, i32), p1: (f32, f32), p2: (f32, f32)) -> SvgRenderer {
+        let mut renderer = SvgRenderer::new(dimensions);
+        renderer.set_view_fit(ViewFit::Stretch);
+        renderer.set_physics_region(p1, p2);
+        renderer
+    }
+
+    #[test]
+    fn screen_y_axis_points_down() {
+        let renderer = stretched((100, 100), (0.0, 0.0), (10.0, 10.0));
+
+        assert_close(renderer.screen_to_physics((0.0, 0.0)), (0.0, 10.0));
+        assert_close(renderer.screen_to_physics((0.0, 100.0)), (0.0, 0.0));
+        assert_close(renderer.screen_to_physics((100.0, 0.0)), (10.0, 10.0));
+        assert_close(renderer.physics_to_screen((2.0, 3.0)), (20.0, 70.0));
+    }
+
+    #[test]
+    fn offset_non_square_region() {
+        let renderer = stretched((400, 200), (-10.0, 5.0), (30.0, 25.0));
+
+        assert_close(renderer.screen_to_physics((200.0, 100.0)), (10.0, 15.0));
+        assert_close(renderer.screen_to_physics((100.0, 50.0)), (0.0, 20.0));
+        assert_close(renderer.screen_to_physics((400.0, 200.0)), (30.0, 5.0));
+        assert_close(renderer.physics_to_screen((-10.0, 25.0)), (0.0, 0.0));
+    }
+
+    #[test]
+    fn screen_physics_screen_round_trip() {
+        let mut renderer = SvgRenderer::new((640, 360));
+        renderer.set_physics_region((-3.0, 7.0), (5.0, 11.0));
+
+        for fit in [
+            ViewFit::Stretch,
+            ViewFit::Letterbox,
+            ViewFit::FitWidth,
+            ViewFit::Fill,
+        ] {
+            renderer.set_view_fit(fit);
+            for pixel in [(0.0, 0.0), (640.0, 360.0), (12.5, 300.0), (333.0, 17.0)] {
+                let physics = renderer.screen_to_physics(pixel);
+                assert_close(renderer.physics_to_screen(physics), pixel);
+            }
+        }
+    }
+}
@@ -0,0 +1,153 @@
+//! Renders to an image in CPU memory, without a window or GPU
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use skia_safe::{surfaces, Color, EncodedImageFormat, Surface};
+
+use super::{
+    apply_physics_region, draw_circle, draw_line, draw_rectangle, FittedView, Renderer, ViewFit,
+};
+use crate::model::primitive::*;
+use crate::physics::Pose;
+
+/// A draw strategy that uses Skia to draw to a raster surface in memory
+///
+/// Useful for running simulations on machines without a display, such as rendering regression images or the frames of a video.
+pub struct RasterRenderer {
+    surface: Surface,
+
+    view: FittedView,
+
+    /// Where to write each completed frame, if anywhere
+    frame_directory: Option<PathBuf>,
+
+    /// The number of frames completed so far
+    frame_count: u32,
+
+    /// The first failure to write a frame since it was last taken
+    write_error: Option<io::Error>,
+}
+
+impl Renderer for RasterRenderer {
+    fn set_physics_region(&mut self, p1: (f32, f32), p2: (f32, f32)) {
+        self.view.request(p1, p2, self.get_surface_dimensions());
+        apply_physics_region(&mut self.surface, self.view.shown());
+    }
+
+    fn get_physics_view_region(&self) -> ((f32, f32), (f32, f32)) {
+        self.view.shown()
+    }
+
+    fn get_surface_dimensions(&self) -> (i32, i32) {
+        (self.surface.width(), self.surface.height())
+    }
+
+    fn set_view_fit(&mut self, fit: ViewFit) {
+        self.view.set_fit(fit, self.get_surface_dimensions());
+        apply_physics_region(&mut self.surface, self.view.shown());
+    }
+
+    fn get_view_fit(&self) -> ViewFit {
+        self.view.fit()
+    }
+
+    fn resize_surface(&mut self, dimensions: (i32, i32)) {
+        self.surface = Self::create_surface(dimensions);
+
+        // A new surface starts with an identity matrix
+        self.view.refit(self.get_surface_dimensions());
+        apply_physics_region(&mut self.surface, self.view.shown());
+    }
+
+    fn begin_new_frame(&mut self) {
+        self.surface.canvas().clear(skia_safe::Color::BLACK);
+    }
+
+    fn end_frame(&mut self) {
+        if let Some(directory) = &self.frame_directory {
+            let path = directory.join(format!("frame_{:05}.png", self.frame_count));
+            if let Err(error) = self.save_png(&path) {
+                self.write_error.get_or_insert(io::Error::new(
+                    error.kind(),
+                    format!("{}: {error}", path.display()),
+                ));
+            }
+        }
+
+        self.frame_count += 1;
+    }
+
+    fn draw_circle(&mut self, circle: &Circle, pose: &Pose) {
+        draw_circle(self.surface.canvas(), circle, pose);
+    }
+
+    fn draw_rectangle(&mut self, rectangle: &Rectangle, pose: &Pose) {
+        draw_rectangle(self.surface.canvas(), rectangle, pose);
+    }
+
+    fn draw_line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: Color) {
+        draw_line(self.surface.canvas(), from, to, width, color);
+    }
+}
+
+impl RasterRenderer {
+    pub fn new(dimensions: (i32, i32)) -> RasterRenderer {
+        let mut surface = Self::create_surface(dimensions);
+        let view = FittedView::new((surface.width(), surface.height()));
+        apply_physics_region(&mut surface, view.shown());
+
+        RasterRenderer {
+            surface,
+            view,
+            frame_directory: None,
+            frame_count: 0,
+            write_error: None,
+        }
+    }
+
+    /// Write every completed frame to a numbered PNG in a directory, or stop writing frames with `None`
+    ///
+    /// The directory is created if it does not exist. Frames are numbered from the number of frames completed so far.
+    pub fn set_frame_directory(&mut self, directory: Option<PathBuf>) -> io::Result<()> {
+        if let Some(directory) = &directory {
+            fs::create_dir_all(directory)?;
+        }
+
+        self.frame_directory = directory;
+        Ok(())
+    }
+
+    /// The number of frames completed so far
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Take the first error from writing a frame to the frame directory since the last call
+    ///
+    /// Drawing carries on after a frame fails to write, so anything writing frames should check this after each one.
+    pub fn take_write_error(&mut self) -> Option<io::Error> {
+        self.write_error.take()
+    }
+
+    /// Encode the current contents of the surface as a PNG
+    pub fn encode_png(&mut self) -> Option<Vec<u8>> {
+        let image = self.surface.image_snapshot();
+        let data = image.encode(None, EncodedImageFormat::PNG, None)?;
+        Some(data.as_bytes().to_vec())
+    }
+
+    /// Write the current contents of the surface to a PNG file
+    pub fn save_png(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let png = self
+            .encode_png()
+            .ok_or_else(|| io::Error::other("failed to encode PNG"))?;
+        fs::write(path, png)
+    }
+
+    /// Create a new surface
+    fn create_surface(dimensions: (i32, i32)) -> Surface {
+        surfaces::raster_n32_premul(dimensions).unwrap()
+    }
+}
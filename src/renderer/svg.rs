@@ -0,0 +1,226 @@
+//! Renders to SVG documents for crisp vector figures
+
+use std::fmt::Write as _;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use skia_safe::Color;
+
+use super::{FittedView, Renderer, ViewFit};
+use crate::model::primitive::*;
+use crate::physics::Pose;
+
+/// A draw strategy that writes each frame as an SVG document
+///
+/// Primitives are emitted in physics coordinates inside a group whose transform maps the view region onto the image, flipping the y-axis the same way `SkiaRenderer` does. The result matches the on-screen rendering but scales without pixelation.
+pub struct SvgRenderer {
+    dimensions: (i32, i32),
+
+    view: FittedView,
+
+    /// Elements drawn so far in the current frame
+    elements: String,
+
+    /// The most recently completed frame
+    document: String,
+
+    /// Where to write each completed frame, if anywhere
+    frame_directory: Option<PathBuf>,
+
+    /// The number of frames completed so far
+    frame_count: u32,
+
+    /// The first failure to write a frame since it was last taken
+    write_error: Option<io::Error>,
+}
+
+impl Renderer for SvgRenderer {
+    fn set_physics_region(&mut self, p1: (f32, f32), p2: (f32, f32)) {
+        self.view.request(p1, p2, self.dimensions);
+    }
+
+    fn get_physics_view_region(&self) -> ((f32, f32), (f32, f32)) {
+        self.view.shown()
+    }
+
+    fn get_surface_dimensions(&self) -> (i32, i32) {
+        self.dimensions
+    }
+
+    fn set_view_fit(&mut self, fit: ViewFit) {
+        self.view.set_fit(fit, self.dimensions);
+    }
+
+    fn get_view_fit(&self) -> ViewFit {
+        self.view.fit()
+    }
+
+    fn resize_surface(&mut self, dimensions: (i32, i32)) {
+        self.dimensions = dimensions;
+        self.view.refit(dimensions);
+    }
+
+    fn begin_new_frame(&mut self) {
+        self.elements.clear();
+    }
+
+    fn end_frame(&mut self) {
+        let (width, height) = self.dimensions;
+        let ((x1, y1), (x2, y2)) = self.view.shown();
+
+        // The same operations as the canvas matrix in `apply_physics_region`: flip the y-axis, scale the region to the image, and move the region origin to the corner
+        let scale_x = width as f32 / (x2 - x1);
+        let scale_y = height as f32 / (y2 - y1);
+        let transform = format!(
+            "matrix({} 0 0 {} {} {})",
+            scale_x,
+            -scale_y,
+            0.0 - x1 * scale_x,
+            height as f32 + y1 * scale_y
+        );
+
+        self.document.clear();
+        let _ = write!(
+            self.document,
+            concat!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{width}\" height=\"{height}\" viewBox=\"0 0 {width} {height}\">\n",
+                "<rect width=\"{width}\" height=\"{height}\" fill=\"#000000\"/>\n",
+                "<g transform=\"{transform}\">\n",
+                "{elements}",
+                "</g>\n",
+                "</svg>\n"
+            ),
+            width = width,
+            height = height,
+            transform = transform,
+            elements = self.elements
+        );
+
+        if let Some(directory) = &self.frame_directory {
+            let path = directory.join(format!("frame_{:05}.svg", self.frame_count));
+            if let Err(error) = fs::write(&path, &self.document) {
+                self.write_error.get_or_insert(io::Error::new(
+                    error.kind(),
+                    format!("{}: {error}", path.display()),
+                ));
+            }
+        }
+
+        self.frame_count += 1;
+    }
+
+    fn draw_circle(&mut self, circle: &Circle, pose: &Pose) {
+        let _ = writeln!(
+            self.elements,
+            "<circle transform=\"{}\" cx=\"{}\" cy=\"{}\" r=\"{}\" {}/>",
+            pose_transform(pose),
+            circle.origin.0,
+            circle.origin.1,
+            circle.radius,
+            paint("fill", circle.color)
+        );
+    }
+
+    fn draw_rectangle(&mut self, rectangle: &Rectangle, pose: &Pose) {
+        let _ = writeln!(
+            self.elements,
+            "<rect transform=\"{}\" x=\"{}\" y=\"{}\" width=\"{}\" height=\"{}\" {}/>",
+            pose_transform(pose),
+            rectangle.origin.0,
+            rectangle.origin.1,
+            rectangle.dimensions.0,
+            rectangle.dimensions.1,
+            paint("fill", rectangle.color)
+        );
+    }
+
+    fn draw_line(&mut self, from: (f32, f32), to: (f32, f32), width: f32, color: Color) {
+        let _ = writeln!(
+            self.elements,
+            "<line x1=\"{}\" y1=\"{}\" x2=\"{}\" y2=\"{}\" stroke-width=\"{}\" {}/>",
+            from.0,
+            from.1,
+            to.0,
+            to.1,
+            width,
+            paint("stroke", color)
+        );
+    }
+}
+
+/// An SVG transform moving an element into the local frame of a pose, matching `apply_pose`
+fn pose_transform(pose: &Pose) -> String {
+    format!(
+        "translate({} {}) rotate({})",
+        pose.position.0,
+        pose.position.1,
+        pose.orientation.to_degrees()
+    )
+}
+
+/// SVG attributes painting the fill or stroke of an element with a color
+fn paint(property: &str, color: Color) -> String {
+    let mut attributes = format!(
+        "{property}=\"#{:02x}{:02x}{:02x}\"",
+        color.r(),
+        color.g(),
+        color.b()
+    );
+    if color.a() != 255 {
+        let _ = write!(
+            attributes,
+            " {property}-opacity=\"{}\"",
+            color.a() as f32 / 255.0
+        );
+    }
+    attributes
+}
+
+impl SvgRenderer {
+    pub fn new(dimensions: (i32, i32)) -> SvgRenderer {
+        SvgRenderer {
+            dimensions,
+            view: FittedView::new(dimensions),
+            elements: String::new(),
+            document: String::new(),
+            frame_directory: None,
+            frame_count: 0,
+            write_error: None,
+        }
+    }
+
+    /// Write every completed frame to a numbered SVG in a directory, or stop writing frames with `None`
+    ///
+    /// The directory is created if it does not exist.
+    pub fn set_frame_directory(&mut self, directory: Option<PathBuf>) -> io::Result<()> {
+        if let Some(directory) = &directory {
+            fs::create_dir_all(directory)?;
+        }
+
+        self.frame_directory = directory;
+        Ok(())
+    }
+
+    /// The number of frames completed so far
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Take the first error from writing a frame to the frame directory since the last call
+    ///
+    /// Drawing carries on after a frame fails to write, so anything writing frames should check this after each one.
+    pub fn take_write_error(&mut self) -> Option<io::Error> {
+        self.write_error.take()
+    }
+
+    /// The SVG document of the most recently completed frame, or an empty string before the first frame
+    pub fn document(&self) -> &str {
+        &self.document
+    }
+
+    /// Write the most recently completed frame to an SVG file
+    pub fn save_svg(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, &self.document)
+    }
+}
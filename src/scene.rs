@@ -0,0 +1,663 @@
+//! Declarative scene files
+//!
+//! A scene describes the objects in a simulation, their models, starting poses and motion, along with the initial view and the physics settings. Scenes are stored as JSON so they are easy to write by hand.
+
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::model::Primitive;
+use crate::physics::bounds::WorldBounds;
+use crate::physics::force_field::BuiltinField;
+use crate::physics::gravitation::NBodyGravity;
+use crate::physics::joint::{JointKind, Spring};
+use crate::physics::material::{CombineRule, Material};
+use crate::physics::spring_network::{Link, Pressure, SpringNetwork};
+use crate::physics::{vector, Body, BodyId, Pose};
+use crate::renderer;
+use crate::simulation::Simulation;
+use serde::{Deserialize, Serialize};
+
+/// A complete simulation setup
+#[derive(Serialize, Deserialize)]
+pub struct Scene {
+    /// The corners of the region of the physics simulation shown at the start
+    pub view_region: ((f32, f32), (f32, f32)),
+
+    /// Coefficient of restitution for objects without a material
+    #[serde(default = "default_restitution")]
+    pub restitution: f32,
+
+    /// Static and dynamic friction coefficient for objects without a material
+    #[serde(default = "default_friction")]
+    pub friction: f32,
+
+    /// Materials objects can refer to by name, alongside the built-in presets. A material here replaces a built-in preset with the same name.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub materials: BTreeMap<String, Material>,
+
+    /// How the restitution of two touching materials is combined
+    #[serde(default)]
+    pub restitution_combine: CombineRule,
+
+    /// How the friction coefficients of two touching materials are combined
+    #[serde(default)]
+    pub friction_combine: CombineRule,
+
+    /// Acceleration applied to every non-static object
+    #[serde(default)]
+    pub gravity: (f32, f32),
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub force_fields: Vec<BuiltinField>,
+
+    /// Mutual attraction between every pair of non-static objects
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub n_body_gravity: Option<NBodyGravity>,
+
+    /// The region objects are kept within
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub bounds: Option<WorldBounds>,
+
+    pub objects: Vec<ObjectDescription>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub joints: Vec<JointDescription>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub spring_networks: Vec<SpringNetworkDescription>,
+}
+
+/// One object in a scene
+#[derive(Serialize, Deserialize)]
+pub struct ObjectDescription {
+    /// The graphics model, which also decides the collision shape
+    pub model: Primitive,
+
+    #[serde(default)]
+    pub position: (f32, f32),
+
+    /// Rotation in radians, counter-clockwise
+    #[serde(default)]
+    pub orientation: f32,
+
+    #[serde(default)]
+    pub velocity: (f32, f32),
+
+    /// Counter-clockwise rotation speed in radians per second
+    #[serde(default)]
+    pub angular_velocity: f32,
+
+    /// What the object is made of, by name or given in full
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub material: Option<MaterialChoice>,
+
+    /// Mass per unit area in place of the material's density. The density decides the mass and inertia unless they are given directly.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub density: Option<f32>,
+
+    /// Mass in place of the one from the density. Unless the inertia is also given, it is scaled by the same factor.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mass: Option<f32>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub inertia: Option<f32>,
+
+    /// Whether the object is immovable scenery
+    #[serde(default, rename = "static")]
+    pub is_static: bool,
+
+    /// Whether other objects bounce off of this one
+    #[serde(default = "default_collides")]
+    pub collides: bool,
+
+    /// Whether the object is swept along its path so it cannot pass through thin objects when moving fast. Only circles can be bullets.
+    #[serde(default)]
+    pub bullet: bool,
+}
+
+/// One joint in a scene
+#[derive(Serialize, Deserialize)]
+pub struct JointDescription {
+    /// The positions of the two connected objects in the scene's object list
+    pub a: usize,
+    pub b: usize,
+
+    /// Where the joint attaches to each object, in world coordinates. Defaults to the object's position.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor_a: Option<(f32, f32)>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub anchor_b: Option<(f32, f32)>,
+
+    #[serde(flatten)]
+    pub kind: JointKind,
+
+    /// The relative orientation of the objects at which the joint angle is zero. Defaults to their relative orientation at the start.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reference_angle: Option<f32>,
+
+    /// Whether the two objects still collide with each other
+    #[serde(default)]
+    pub collide_connected: bool,
+}
+
+/// Objects in a scene linked by springs
+#[derive(Serialize, Deserialize)]
+pub struct SpringNetworkDescription {
+    pub links: Vec<LinkDescription>,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pressure: Option<PressureDescription>,
+}
+
+/// A spring between two objects in a scene
+#[derive(Serialize, Deserialize)]
+pub struct LinkDescription {
+    /// The positions of the two linked objects in the scene's object list
+    pub a: usize,
+    pub b: usize,
+
+    /// Defaults to the distance between the objects at the start
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rest_length: Option<f32>,
+
+    #[serde(flatten)]
+    pub spring: Spring,
+}
+
+/// Gas filling a loop of objects in a scene
+#[derive(Serialize, Deserialize)]
+pub struct PressureDescription {
+    /// The positions of the objects in the scene's object list, going either way around the loop
+    pub outline: Vec<usize>,
+
+    /// Defaults to the area inside the loop at the start
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rest_area: Option<f32>,
+
+    pub stiffness: f32,
+}
+
+/// A material named in the scene or built in, or a material written out in full
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum MaterialChoice {
+    Named(String),
+    Custom(Material),
+}
+
+fn default_restitution() -> f32 {
+    Material::DEFAULT.restitution
+}
+
+fn default_friction() -> f32 {
+    Material::DEFAULT.dynamic_friction
+}
+
+fn default_collides() -> bool {
+    true
+}
+
+/// An error from reading or writing a scene file
+#[derive(Debug)]
+pub enum SceneError {
+    Io(io::Error),
+    Format(serde_json::Error),
+    /// An object refers to a material that is neither in the scene nor built in
+    UnknownMaterial(String),
+    /// A joint refers to an object past the end of the object list, or connects an object to itself
+    InvalidJoint(usize),
+    /// A spring network refers to an object past the end of the object list
+    InvalidSpringNetwork(usize),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io(error) => write!(f, "could not access file: {error}"),
+            SceneError::Format(error) => write!(f, "invalid file contents: {error}"),
+            SceneError::UnknownMaterial(name) => write!(f, "unknown material {name:?}"),
+            SceneError::InvalidJoint(index) => {
+                write!(f, "joint {index} does not connect two different objects")
+            }
+            SceneError::InvalidSpringNetwork(index) => {
+                write!(f, "spring network {index} refers to a missing object")
+            }
+        }
+    }
+}
+
+impl Error for SceneError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SceneError::Io(error) => Some(error),
+            SceneError::Format(error) => Some(error),
+            SceneError::UnknownMaterial(_)
+            | SceneError::InvalidJoint(_)
+            | SceneError::InvalidSpringNetwork(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for SceneError {
+    fn from(error: io::Error) -> SceneError {
+        SceneError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for SceneError {
+    fn from(error: serde_json::Error) -> SceneError {
+        SceneError::Format(error)
+    }
+}
+
+impl Scene {
+    /// Parse a scene from JSON
+    pub fn from_json(json: &str) -> Result<Scene, SceneError> {
+        let scene: Scene = serde_json::from_str(json)?;
+
+        for description in &scene.objects {
+            if let Some(MaterialChoice::Named(name)) = &description.material {
+                if scene.named_material(name).is_none() {
+                    return Err(SceneError::UnknownMaterial(name.clone()));
+                }
+            }
+        }
+
+        for (index, joint) in scene.joints.iter().enumerate() {
+            let count = scene.objects.len();
+            if joint.a >= count || joint.b >= count || joint.a == joint.b {
+                return Err(SceneError::InvalidJoint(index));
+            }
+        }
+
+        for (index, network) in scene.spring_networks.iter().enumerate() {
+            let linked = network.links.iter().flat_map(|link| [link.a, link.b]);
+            let outline = network
+                .pressure
+                .iter()
+                .flat_map(|pressure| &pressure.outline);
+            if linked
+                .chain(outline.copied())
+                .any(|object| object >= scene.objects.len())
+            {
+                return Err(SceneError::InvalidSpringNetwork(index));
+            }
+        }
+
+        Ok(scene)
+    }
+
+    /// Look up a material defined in the scene, or else a built-in preset
+    pub fn named_material(&self, name: &str) -> Option<Material> {
+        self.materials
+            .get(name)
+            .copied()
+            .or_else(|| Material::preset(name))
+    }
+
+    /// The material an object is made of
+    ///
+    /// Objects without a material use the scene's restitution and friction. Unknown names, which `from_json` rejects, give the default material.
+    pub fn object_material(&self, description: &ObjectDescription) -> Material {
+        let material = match &description.material {
+            None => Material {
+                restitution: self.restitution,
+                static_friction: self.friction,
+                dynamic_friction: self.friction,
+                ..Material::DEFAULT
+            },
+            Some(MaterialChoice::Named(name)) => self.named_material(name).unwrap_or_default(),
+            Some(MaterialChoice::Custom(material)) => *material,
+        };
+
+        Material {
+            density: description.density.unwrap_or(material.density),
+            ..material
+        }
+    }
+
+    /// Write the scene as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        // Every field is plain data, so serialization cannot fail
+        serde_json::to_string_pretty(self).unwrap()
+    }
+
+    /// Read a scene file
+    pub fn load(path: impl AsRef<Path>) -> Result<Scene, SceneError> {
+        Scene::from_json(&fs::read_to_string(path)?)
+    }
+
+    /// Write the scene to a file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SceneError> {
+        Ok(fs::write(path, self.to_json())?)
+    }
+
+    /// Add the scene's objects to a simulation and apply its view and physics settings
+    pub fn build<R: renderer::Renderer>(&self, simulation: &mut Simulation<R>) {
+        simulation.set_view_region(self.view_region);
+        simulation.home_view_region = self.view_region;
+
+        simulation.physics.restitution_combine = self.restitution_combine;
+        simulation.physics.friction_combine = self.friction_combine;
+        simulation.physics.gravity = self.gravity;
+        simulation.physics.n_body_gravity = self.n_body_gravity;
+        simulation.physics.bounds = self.bounds;
+        for field in &self.force_fields {
+            simulation.physics.add_force_field(Box::new(*field));
+        }
+
+        let mut ids = Vec::new();
+        for description in &self.objects {
+            let id = simulation
+                .add_object_with_model_at_pos(description.model.clone(), description.position)
+                .physics_body;
+            ids.push(id);
+            let body = simulation.physics.get_object_mut(id).unwrap();
+
+            // Turn the object about the model's origin, where the body position is its center
+            let center = vector::sub(body.pose.position, description.position);
+            body.pose = Pose {
+                position: vector::add(
+                    description.position,
+                    vector::rotate(center, description.orientation),
+                ),
+                orientation: description.orientation,
+            };
+            body.previous_pose = body.pose;
+            body.collides = description.collides;
+            body.bullet = description.bullet;
+            body.set_material(self.object_material(description));
+
+            let dynamics = &mut body.dynamics;
+            if description.is_static {
+                dynamics.set_static();
+                continue;
+            }
+
+            if let Some(mass) = description.mass {
+                // The same shape made heavier is harder to turn by the same factor
+                let scale = mass / dynamics.mass();
+                if description.inertia.is_none() && scale.is_finite() {
+                    dynamics.set_inertia(dynamics.inertia() * scale);
+                }
+                dynamics.set_mass(mass);
+            }
+            if let Some(inertia) = description.inertia {
+                dynamics.set_inertia(inertia);
+            }
+            dynamics.velocity = description.velocity;
+            dynamics.angular_velocity = description.angular_velocity;
+        }
+
+        for description in &self.joints {
+            let (a, b) = (ids[description.a], ids[description.b]);
+            let position = |id| simulation.physics.get_object(id).unwrap().pose.position;
+            let anchor_a = description.anchor_a.unwrap_or_else(|| position(a));
+            let anchor_b = description.anchor_b.unwrap_or_else(|| position(b));
+
+            // Both objects exist and differ, which `from_json` checks
+            let Some(joint) =
+                simulation
+                    .physics
+                    .add_joint(a, b, anchor_a, anchor_b, description.kind)
+            else {
+                continue;
+            };
+            joint.collide_connected = description.collide_connected;
+            if let Some(reference_angle) = description.reference_angle {
+                joint.reference_angle = reference_angle;
+            }
+        }
+
+        for description in &self.spring_networks {
+            let physics = &simulation.physics;
+            let position = |index: usize| physics.get_object(ids[index]).unwrap().pose.position;
+
+            let links = description
+                .links
+                .iter()
+                .map(|link| Link {
+                    a: ids[link.a],
+                    b: ids[link.b],
+                    rest_length: link.rest_length.unwrap_or_else(|| {
+                        vector::length(vector::sub(position(link.b), position(link.a)))
+                    }),
+                    spring: link.spring,
+                })
+                .collect();
+
+            let pressure = description.pressure.as_ref().map(|pressure| {
+                let outline: Vec<_> = pressure.outline.iter().map(|&index| ids[index]).collect();
+                let gas = Pressure::around(physics, &outline, pressure.stiffness);
+                Pressure {
+                    rest_area: pressure.rest_area.unwrap_or(gas.rest_area),
+                    ..gas
+                }
+            });
+
+            simulation
+                .physics
+                .add_spring_network(SpringNetwork { links, pressure });
+        }
+    }
+
+    /// Describe the current state of a simulation
+    ///
+    /// Materials, mass and inertia are written out directly, so a saved scene reproduces the bodies exactly even if their mass no longer matches their density. Custom force fields cannot be described and are left out, as are joints and springs to bodies without an object.
+    pub fn capture<R: renderer::Renderer>(simulation: &Simulation<R>) -> Scene {
+        let bodies: Vec<&Body> = simulation
+            .objects()
+            .iter()
+            .filter_map(|object| simulation.physics.get_object(object.physics_body))
+            .collect();
+
+        let objects = simulation
+            .objects()
+            .iter()
+            .filter_map(|object| {
+                let body = simulation.physics.get_object(object.physics_body)?;
+                let is_static = body.dynamics.is_static();
+
+                Some(ObjectDescription {
+                    model: object.graphics_model.clone(),
+                    position: body.pose.position,
+                    orientation: body.pose.orientation,
+                    velocity: body.dynamics.velocity,
+                    angular_velocity: body.dynamics.angular_velocity,
+                    material: Some(MaterialChoice::Custom(body.material)),
+                    density: None,
+                    mass: (!is_static).then(|| body.dynamics.mass()),
+                    inertia: (!is_static).then(|| body.dynamics.inertia()),
+                    is_static,
+                    collides: body.collides,
+                    bullet: body.bullet,
+                })
+            })
+            .collect();
+
+        // Bodies are listed in the same order as the objects written out
+        let index = |id: BodyId| bodies.iter().position(|body| body.id == id);
+
+        let joints = simulation
+            .physics
+            .joints()
+            .iter()
+            .filter_map(|joint| {
+                let (a, b) = (index(joint.a)?, index(joint.b)?);
+
+                Some(JointDescription {
+                    a,
+                    b,
+                    anchor_a: Some(joint.anchor_a(&bodies[a].pose)),
+                    anchor_b: Some(joint.anchor_b(&bodies[b].pose)),
+                    kind: joint.kind,
+                    reference_angle: Some(joint.reference_angle),
+                    collide_connected: joint.collide_connected,
+                })
+            })
+            .collect();
+
+        let spring_networks = simulation
+            .physics
+            .spring_networks()
+            .iter()
+            .map(|network| SpringNetworkDescription {
+                links: network
+                    .links
+                    .iter()
+                    .filter_map(|link| {
+                        Some(LinkDescription {
+                            a: index(link.a)?,
+                            b: index(link.b)?,
+                            rest_length: Some(link.rest_length),
+                            spring: link.spring,
+                        })
+                    })
+                    .collect(),
+                pressure: network.pressure.as_ref().and_then(|pressure| {
+                    Some(PressureDescription {
+                        outline: pressure
+                            .outline
+                            .iter()
+                            .map(|&id| index(id))
+                            .collect::<Option<Vec<usize>>>()?,
+                        rest_area: Some(pressure.rest_area),
+                        stiffness: pressure.stiffness,
+                    })
+                }),
+            })
+            .collect();
+
+        Scene {
+            view_region: simulation.view_region(),
+            restitution: default_restitution(),
+            friction: default_friction(),
+            materials: BTreeMap::new(),
+            restitution_combine: simulation.physics.restitution_combine,
+            friction_combine: simulation.physics.friction_combine,
+            gravity: simulation.physics.gravity,
+            force_fields: simulation
+                .physics
+                .force_fields()
+                .iter()
+                .filter_map(|field| field.to_builtin())
+                .collect(),
+            n_body_gravity: simulation.physics.n_body_gravity,
+            bounds: simulation.physics.bounds,
+            objects,
+            joints,
+            spring_networks,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::renderer::SvgRenderer;
+
+    fn build(scene: &Scene) -> Simulation<SvgRenderer> {
+        let mut simulation = Simulation::new(SvgRenderer::new((100, 100)));
+        scene.build(&mut simulation);
+        simulation
+    }
+
+    /// Compare two scenes as JSON, allowing numbers a little rounding since joint anchors go to the bodies' own coordinates and back
+    fn assert_same_scene(actual: &serde_json::Value, expected: &serde_json::Value, path: &str) {
+        use serde_json::Value;
+
+        match (actual, expected) {
+            (Value::Number(a), Value::Number(e)) => {
+                let (a, e) = (a.as_f64().unwrap(), e.as_f64().unwrap());
+                assert!(
+                    (a - e).abs() <= 1e-5 * e.abs().max(1.0),
+                    "{path}: expected {e}, got {a}"
+                );
+            }
+            (Value::Array(a), Value::Array(e)) => {
+                assert_eq!(a.len(), e.len(), "{path}");
+                for (i, (a, e)) in a.iter().zip(e).enumerate() {
+                    assert_same_scene(a, e, &format!("{path}[{i}]"));
+                }
+            }
+            (Value::Object(a), Value::Object(e)) => {
+                assert_eq!(
+                    a.keys().collect::<Vec<_>>(),
+                    e.keys().collect::<Vec<_>>(),
+                    "{path}"
+                );
+                for (key, e) in e {
+                    assert_same_scene(&a[key], e, &format!("{path}.{key}"));
+                }
+            }
+            _ => assert_eq!(actual, expected, "{path}"),
+        }
+    }
+
+    #[test]
+    fn captured_scenes_survive_a_save_and_load() {
+        let path =
+            std::env::temp_dir().join(format!("scene_round_trip_{}.json", std::process::id()));
+
+        for entry in fs::read_dir("scenes").unwrap() {
+            let source = entry.unwrap().path();
+            let mut simulation = build(&Scene::load(&source).unwrap());
+
+            // Capture partway through, once objects have moved
+            for _ in 0..30 {
+                simulation.update(Duration::from_secs_f32(1.0 / 60.0));
+            }
+            let captured = Scene::capture(&simulation);
+            captured.save(&path).unwrap();
+
+            let rebuilt = build(&Scene::load(&path).unwrap());
+            assert_same_scene(
+                &serde_json::to_value(Scene::capture(&rebuilt)).unwrap(),
+                &serde_json::to_value(&captured).unwrap(),
+                &source.display().to_string(),
+            );
+        }
+
+        let _ = fs::remove_file(path);
+    }
+
+    #[test]
+    fn mass_override_scales_inertia() {
+        let json = |mass: &str| {
+            format!(
+                r##"{{
+                    "view_region": [[0, 0], [10, 10]],
+                    "objects": [{{
+                        "model": {{ "type": "rectangle", "origin": [-1, -0.5], "dimensions": [2, 1], "color": "#ffffff" }}
+                        {mass}
+                    }}]
+                }}"##
+            )
+        };
+        let dynamics = |json: &str| {
+            let simulation = build(&Scene::from_json(json).unwrap());
+            let body = &simulation.physics.objects()[0];
+            (body.dynamics.mass(), body.dynamics.inertia())
+        };
+
+        let (mass, inertia) = dynamics(&json(""));
+        let (heavier_mass, heavier_inertia) =
+            dynamics(&json(&format!(r#", "mass": {}"#, mass * 3.0)));
+        assert!((heavier_mass - mass * 3.0).abs() < 1e-4);
+        assert!(
+            (heavier_inertia - inertia * 3.0).abs() < 1e-4 * inertia,
+            "inertia {inertia} became {heavier_inertia}"
+        );
+
+        // Inertia given alongside the mass is kept as it is
+        let (_, given_inertia) =
+            dynamics(&json(&format!(r#", "mass": {}, "inertia": 7"#, mass * 3.0)));
+        assert_eq!(given_inertia, 7.0);
+    }
+}
@@ -1,14 +1,24 @@
 //! Contains root components of the physics simulator including the controller, objects, and inputs.
 
+pub mod history;
+
 use std::default::Default;
 use std::time::Duration;
 use std::vec::Vec;
 
+use serde::{Deserialize, Serialize};
+use skia_safe::Color;
+
 use crate::model::Primitive;
-use crate::physics::{BodyId, Circle, PhysicsEngine};
+use crate::physics::bounds::EdgeBehavior;
+use crate::physics::joint::Spring;
+use crate::physics::spring_network::{SpringNetwork, SpringNetworkId};
+use crate::physics::{self, vector, BodyId, Circle, PhysicsEngine};
 use crate::renderer;
+use history::{History, Recording, Snapshot};
 
 /// An object in the 2D simulation
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Object {
     pub graphics_model: Primitive,
     pub physics_body: BodyId,
@@ -22,6 +32,12 @@ pub struct Inputs {
     pub view_region_scroll_speed_multiplier: f32,
     pub view_region_zoom_speed: f32,
     pub view_region_zoom_speed_multiplier: f32,
+
+    /// The cursor position in physics coordinates
+    pub cursor_position: (f32, f32),
+
+    /// The cursor position in surface pixels, once it has been over the surface. The physics position is worked out from it again every update, so it stays under the cursor while the view scrolls and zooms.
+    pub cursor_pixel: Option<(f32, f32)>,
 }
 
 impl Default for Inputs {
@@ -31,10 +47,21 @@ impl Default for Inputs {
             view_region_scroll_speed_multiplier: 1.0,
             view_region_zoom_speed: 0.0,
             view_region_zoom_speed_multiplier: 1.0,
+            cursor_position: (0.0, 0.0),
+            cursor_pixel: None,
         }
     }
 }
 
+/// A body held by the cursor
+///
+/// The grabbed point is pulled toward the cursor by a damped spring, like a mouse joint, so the body swings naturally and still collides with everything else.
+struct Drag {
+    body: BodyId,
+    /// The grabbed point relative to the body position, before rotation
+    local_anchor: (f32, f32),
+}
+
 /// The root controller of the 2D simulation
 pub struct Simulation<Renderer: renderer::Renderer> {
     /// A list of all objects in the simulation.
@@ -45,8 +72,66 @@ pub struct Simulation<Renderer: renderer::Renderer> {
     /// A counter for unique object IDs. Hopefully this will never overflow...
     object_uid_counter: u32,
 
+    /// Wall-clock time in seconds that has not yet been simulated
     dt_accum: f32,
 
+    /// How far the drawn frame lies between the previous and current physics step, from 0.0 to 1.0
+    interpolation_alpha: f32,
+
+    /// The fixed amount of time each physics update advances
+    ///
+    /// Physics always advances in steps of this size regardless of frame rate, so runs are reproducible.
+    pub physics_step: Duration,
+
+    /// The most physics steps to run for one frame
+    ///
+    /// If the physics cannot keep up with wall-clock time, the remaining time is dropped rather than letting the backlog grow forever.
+    pub max_substeps: u32,
+
+    /// Whether physics is stopped. Single steps can still be taken with `step_once`.
+    pub paused: bool,
+
+    /// How fast simulated time passes relative to wall-clock time. Below 1.0 is slow motion and above is fast forward.
+    pub time_scale: f32,
+
+    /// Single steps requested with `step_once` that have not run yet
+    pending_steps: u32,
+
+    /// The body currently being dragged with the cursor
+    drag: Option<Drag>,
+
+    /// Where the cursor was at the last update, for measuring its velocity
+    last_cursor_position: (f32, f32),
+
+    /// The smoothed velocity of the cursor in physics units per second
+    cursor_velocity: (f32, f32),
+
+    /// How many times per second a dragged body oscillates about the cursor
+    pub drag_frequency: f32,
+
+    /// How strongly the drag spring is damped. 1.0 is critically damped.
+    pub drag_damping_ratio: f32,
+
+    /// Snapshots of the most recent physics steps, for rewinding. Nothing is recorded until it is given a capacity.
+    pub history: History,
+
+    /// The index of the snapshot being shown while looking back through the history, or `None` while running live
+    history_position: Option<usize>,
+
+    /// Whether to advance through the history at the physics rate rather than holding on one snapshot
+    playing_history: bool,
+
+    /// Whether to draw the world bounds over the objects
+    pub show_bounds: bool,
+
+    /// The view region to return to when the view is reset
+    pub home_view_region: ((f32, f32), (f32, f32)),
+
+    /// The region of the physics simulation asked to be shown, before the renderer fits it to the surface
+    ///
+    /// Scrolling and zooming move this region rather than the fitted one, so a crop or letterbox from one view fit or window size is never carried into the next.
+    view_region: ((f32, f32), (f32, f32)),
+
     pub renderer: Renderer,
 
     pub physics: PhysicsEngine,
@@ -64,6 +149,24 @@ where
             objects: Vec::new(),
             object_uid_counter: 0,
             dt_accum: 0.0,
+            interpolation_alpha: 0.0,
+            physics_step: Duration::from_secs_f64(1.0 / 120.0),
+            max_substeps: 8,
+            paused: false,
+            time_scale: 1.0,
+            pending_steps: 0,
+            drag: None,
+            last_cursor_position: (0.0, 0.0),
+            cursor_velocity: (0.0, 0.0),
+            drag_frequency: 5.0,
+            drag_damping_ratio: 0.7,
+            // Recording is opt-in, since every snapshot copies the whole simulation
+            history: History::new(0),
+            history_position: None,
+            playing_history: false,
+            show_bounds: false,
+            home_view_region: renderer.get_physics_view_region(),
+            view_region: renderer.get_physics_view_region(),
             renderer,
             physics: PhysicsEngine::new(),
             inputs: Inputs::default(),
@@ -71,7 +174,9 @@ where
     }
 
     pub fn update(&mut self, delta_time: Duration) {
-        self.dt_accum += delta_time.as_secs_f32();
+        if !self.paused {
+            self.dt_accum += delta_time.as_secs_f32() * self.time_scale.max(0.0);
+        }
         if self.inputs.view_region_scroll_speed.0 != 0.0
             || self.inputs.view_region_scroll_speed.1 != 0.0
             || self.inputs.view_region_zoom_speed != 0.0
@@ -83,7 +188,7 @@ where
                 * delta_time.as_secs_f32()
                 * self.inputs.view_region_scroll_speed_multiplier;
 
-            let (mut p1, mut p2) = self.renderer.get_physics_view_region();
+            let (mut p1, mut p2) = self.view_region;
 
             p1.0 += delta_x;
             p1.1 += delta_y;
@@ -111,37 +216,314 @@ where
             p2.0 = center.0 + (p2.0 - center.0) * delta_size; // x2
             p2.1 = center.1 + (p2.1 - center.1) * delta_size; // y2
 
-            self.renderer.set_physics_region(p1, p2);
+            self.set_view_region((p1, p2));
+        }
+
+        // The view may have moved under a cursor that stayed still
+        if let Some(pixel) = self.inputs.cursor_pixel {
+            self.inputs.cursor_position = self.renderer.screen_to_physics(pixel);
+        }
+        self.track_cursor(delta_time.as_secs_f32());
+
+        self.step_physics();
+    }
+
+    /// The region of the physics simulation asked to be shown. The renderer may show more or less of it to fit the surface.
+    pub fn view_region(&self) -> ((f32, f32), (f32, f32)) {
+        self.view_region
+    }
+
+    /// Ask the renderer to show a region of the physics simulation
+    pub fn set_view_region(&mut self, region: ((f32, f32), (f32, f32))) {
+        self.view_region = region;
+        self.renderer.set_physics_region(region.0, region.1);
+    }
+
+    /// Run as many fixed physics steps as the accumulated time allows
+    fn step_physics(&mut self) {
+        let step = self.physics_step.as_secs_f32();
+
+        for _ in 0..std::mem::take(&mut self.pending_steps) {
+            self.tick();
+        }
+
+        let mut substeps = 0;
+        while self.dt_accum >= step && substeps < self.max_substeps {
+            self.tick();
+            self.dt_accum -= step;
+            substeps += 1;
+        }
+
+        // Avoid the spiral of death: drop whole steps we could not keep up with, but keep the fraction for interpolation
+        if self.dt_accum >= step {
+            self.dt_accum %= step;
+        }
+
+        // While paused, show exactly the latest state so single steps can be inspected
+        self.interpolation_alpha = if self.paused {
+            1.0
+        } else {
+            self.dt_accum / step
+        };
+    }
+
+    /// Advance by one physics step, or to the next snapshot when playing back the history
+    fn tick(&mut self) {
+        match self.history_position {
+            None => {
+                self.apply_drag_force();
+                self.physics.update(self.physics_step);
+                self.remove_despawned_objects();
+                if self.history.capacity() > 0 {
+                    self.history.record(self.snapshot());
+                }
+            }
+            Some(position) if self.playing_history => {
+                if position + 1 < self.history.len() {
+                    self.show_history(position + 1);
+                } else {
+                    self.playing_history = false;
+                }
+            }
+            // Hold on the snapshot being looked at
+            Some(_) => {}
+        }
+    }
+
+    /// Advance exactly one physics step at the next update, even while paused
+    ///
+    /// While looking back through the history this moves one snapshot forward instead.
+    pub fn step_once(&mut self) {
+        if self.is_viewing_history() {
+            self.scrub(1);
+        } else {
+            self.pending_steps += 1;
+        }
+    }
+
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    /// Measure the cursor velocity, smoothing out the jitter of individual mouse events
+    fn track_cursor(&mut self, dt: f32) {
+        if dt <= 0.0 {
+            return;
+        }
+
+        let cursor = self.inputs.cursor_position;
+        let velocity = vector::scale(vector::sub(cursor, self.last_cursor_position), 1.0 / dt);
+        self.last_cursor_position = cursor;
+
+        // Blend toward the latest velocity over roughly 50 ms regardless of frame rate
+        let blend = 1.0 - (-dt / 0.05).exp();
+        self.cursor_velocity = vector::add(
+            self.cursor_velocity,
+            vector::scale(vector::sub(velocity, self.cursor_velocity), blend),
+        );
+    }
+
+    /// Pull the dragged body toward the cursor for the next physics step
+    fn apply_drag_force(&mut self) {
+        let Some(drag) = &self.drag else {
+            return;
+        };
+        let Some(body) = self.physics.get_object_mut(drag.body) else {
+            // The body was removed while it was held
+            self.drag = None;
+            return;
+        };
+
+        let anchor = vector::add(
+            body.pose.position,
+            vector::rotate(drag.local_anchor, body.pose.orientation),
+        );
+
+        // Scale the spring by the mass so every body follows the cursor equally quickly
+        let omega = 2.0 * std::f32::consts::PI * self.drag_frequency;
+        let mass = body.dynamics.mass();
+        let stiffness = mass * omega * omega;
+        let damping = 2.0 * mass * self.drag_damping_ratio * omega;
+
+        let stretch = vector::sub(self.inputs.cursor_position, anchor);
+        let force = vector::sub(
+            vector::scale(stretch, stiffness),
+            vector::scale(body.velocity_at_point(anchor), damping),
+        );
+        body.apply_force_at_point(force, anchor);
+    }
+
+    /// The topmost movable object whose body contains a point in physics coordinates
+    pub fn object_at_point(&self, point: (f32, f32)) -> Option<&Object> {
+        // Later objects are drawn on top
+        self.objects.iter().rev().find(|object| {
+            self.physics
+                .get_object(object.physics_body)
+                .is_some_and(|body| {
+                    body.collides
+                        && !body.dynamics.is_static()
+                        && body.shape.contains_point(&body.pose, point)
+                })
+        })
+    }
+
+    /// Grab the object under the cursor, returning whether there was one
+    pub fn begin_drag(&mut self) -> bool {
+        let cursor = self.inputs.cursor_position;
+        let Some(object) = self.object_at_point(cursor) else {
+            return false;
+        };
+        let id = object.physics_body;
+        let body = self.physics.get_object(id).unwrap();
+
+        self.drag = Some(Drag {
+            body: id,
+            local_anchor: vector::rotate(
+                vector::sub(cursor, body.pose.position),
+                -body.pose.orientation,
+            ),
+        });
+        true
+    }
+
+    /// Release the dragged object, throwing it with the velocity of the cursor
+    pub fn end_drag(&mut self) {
+        let Some(drag) = self.drag.take() else {
+            return;
+        };
+        if let Some(body) = self.physics.get_object_mut(drag.body) {
+            body.dynamics.velocity = self.cursor_velocity;
+        }
+    }
+
+    /// Capture the complete state of the simulation
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            objects: self.objects.clone(),
+            object_uid_counter: self.object_uid_counter,
+            physics: self.physics.snapshot(),
+        }
+    }
+
+    /// Return the simulation to a captured state
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        self.objects = snapshot.objects.clone();
+        self.object_uid_counter = snapshot.object_uid_counter;
+        self.physics.restore(&snapshot.physics);
+        self.drag = None;
+    }
+
+    fn show_history(&mut self, position: usize) {
+        let snapshot = self.history.get(position).unwrap().clone();
+        self.restore(&snapshot);
+        self.history_position = Some(position);
+    }
+
+    /// Whether the simulation is showing a past snapshot rather than running live
+    pub fn is_viewing_history(&self) -> bool {
+        self.history_position.is_some()
+    }
+
+    /// Move through the history by a number of physics steps, negative to go back in time
+    ///
+    /// The simulation holds on the snapshot reached until `resume` is called. Moving forward past the newest snapshot resumes automatically.
+    pub fn scrub(&mut self, steps: isize) {
+        if self.history.is_empty() {
+            return;
+        }
+
+        let newest = self.history.len() - 1;
+        let position = self.history_position.unwrap_or(newest);
+        let target = position.saturating_add_signed(steps).min(newest);
+
+        self.playing_history = false;
+        if steps > 0 && target == newest {
+            self.show_history(newest);
+            self.resume();
+        } else {
+            self.show_history(target);
         }
+    }
+
+    /// Continue running live from the snapshot being shown, discarding any newer snapshots
+    pub fn resume(&mut self) {
+        if let Some(position) = self.history_position.take() {
+            self.history.truncate(position + 1);
+        }
+        self.playing_history = false;
+    }
 
-        self.physics.update(delta_time);
+    /// Package the history as a recording that can be saved and replayed later
+    pub fn recording(&self) -> Recording {
+        Recording {
+            physics_step: self.physics_step,
+            view_region: self.view_region,
+            physics: self.physics.settings(),
+            snapshots: self.history.iter().cloned().collect(),
+        }
     }
 
+    /// Replace the history with a recording and play it back from the start
+    ///
+    /// Playback restores each recorded snapshot in turn, so the poses are identical to the recording bit for bit. The recorded physics settings are applied too, so `resume` continues simulating from the snapshot being shown just as the original would have.
+    pub fn play_recording(&mut self, recording: Recording) {
+        self.physics_step = recording.physics_step;
+        self.physics.apply_settings(&recording.physics);
+
+        self.set_view_region(recording.view_region);
+        self.home_view_region = recording.view_region;
+
+        self.history = History::new(self.history.capacity().max(recording.snapshots.len()));
+        for snapshot in recording.snapshots {
+            self.history.record(snapshot);
+        }
+
+        if !self.history.is_empty() {
+            self.show_history(0);
+            self.playing_history = true;
+        }
+    }
+
+    /// How far the current frame lies between the previous and current physics step, from 0.0 to 1.0
+    pub fn interpolation_alpha(&self) -> f32 {
+        self.interpolation_alpha
+    }
+
+    /// Add an object with the model's origin at a position
+    ///
+    /// The body is placed at the center of the model's shape so it turns about its center of mass, and the model is moved to be drawn around it. A model centered on its origin is left as it is.
     pub fn add_object_with_model_at_pos(
         &mut self,
-        model: Primitive,
+        mut model: Primitive,
         position: (f32, f32),
     ) -> &mut Object {
-        // Create a physics model for circles only for now
-        let body = match &model {
+        let body = match &mut model {
             Primitive::Circle(circle) => self.physics.add_object(Circle {
-                origin: (0.0, 0.0),
+                origin: circle.origin,
                 radius: circle.radius,
             }),
             Primitive::Rectangle(rectangle) => {
-                // Create the largest possible circle that fits inside the rectangle
                 // The origin of the rectangle model is the bottom-left corner
-                self.physics.add_object(Circle {
+                let half_extents = (rectangle.dimensions.0 / 2.0, rectangle.dimensions.1 / 2.0);
+                self.physics.add_object(physics::Rectangle {
                     origin: (
-                        rectangle.origin.0 + rectangle.dimensions.0 / 2.0,
-                        rectangle.origin.1 + rectangle.dimensions.1 / 2.0,
+                        rectangle.origin.0 + half_extents.0,
+                        rectangle.origin.1 + half_extents.1,
                     ),
-                    radius: (rectangle.dimensions.0 / 2.0).min(rectangle.dimensions.1 / 2.0),
+                    half_extents,
                 })
             }
         };
 
-        body.pose.position = position;
+        // The engine moved the shape's center of mass to the body position, so move the model with it
+        let center = body.pose.position;
+        body.set_position(vector::add(position, center));
+        match &mut model {
+            Primitive::Circle(circle) => circle.origin = vector::sub(circle.origin, center),
+            Primitive::Rectangle(rectangle) => {
+                rectangle.origin = vector::sub(rectangle.origin, center)
+            }
+        }
 
         self.objects.push(Object {
             graphics_model: model,
@@ -153,22 +535,215 @@ where
         self.objects.last_mut().unwrap()
     }
 
+    /// All objects in the order they are drawn
+    pub fn objects(&self) -> &[Object] {
+        &self.objects
+    }
+
     pub fn add_object_with_model(&mut self, model: Primitive) -> &mut Object {
         self.add_object_with_model_at_pos(model, (0.0, 0.0))
     }
 
-    /// Remove an object from the simulation
+    /// Remove an object and its physics body from the simulation
     pub fn remove_object(&mut self, id: u32) {
-        self.objects.retain(|object| object.id != id);
+        if let Some(index) = self.objects.iter().position(|object| object.id == id) {
+            let object = self.objects.remove(index);
+            self.physics.remove_object(object.physics_body);
+        }
+    }
+
+    /// Add an object drawn with the same model at each position, returning their bodies
+    fn add_nodes(&mut self, model: &Primitive, positions: &[(f32, f32)]) -> Vec<BodyId> {
+        positions
+            .iter()
+            .map(|&position| {
+                self.add_object_with_model_at_pos(model.clone(), position)
+                    .physics_body
+            })
+            .collect()
+    }
+
+    /// Add a rope of `nodes` objects drawn with `node_model`, evenly spaced from `from` to `to` and linked by springs
+    ///
+    /// Nothing holds the rope up. Make one of its bodies static to hang it.
+    pub fn add_rope(
+        &mut self,
+        from: (f32, f32),
+        to: (f32, f32),
+        nodes: usize,
+        node_model: Primitive,
+        spring: Spring,
+    ) -> SpringNetworkId {
+        let step = vector::scale(
+            vector::sub(to, from),
+            1.0 / nodes.saturating_sub(1).max(1) as f32,
+        );
+        let positions: Vec<(f32, f32)> = (0..nodes)
+            .map(|i| vector::add(from, vector::scale(step, i as f32)))
+            .collect();
+
+        let bodies = self.add_nodes(&node_model, &positions);
+        let network = SpringNetwork::rope(&self.physics, &bodies, spring);
+        self.physics.add_spring_network(network)
+    }
+
+    /// Add a sheet of cloth with `columns` by `rows` objects drawn with `node_model`, hanging down and to the right of `top_left`
+    ///
+    /// Nodes are `spacing` apart. Nothing holds the cloth up. Make some of its bodies static to pin it.
+    pub fn add_cloth(
+        &mut self,
+        top_left: (f32, f32),
+        spacing: f32,
+        (columns, rows): (usize, usize),
+        node_model: Primitive,
+        spring: Spring,
+    ) -> SpringNetworkId {
+        let positions: Vec<(f32, f32)> = (0..rows)
+            .flat_map(|row| {
+                (0..columns).map(move |column| {
+                    (
+                        top_left.0 + column as f32 * spacing,
+                        top_left.1 - row as f32 * spacing,
+                    )
+                })
+            })
+            .collect();
+
+        let bodies = self.add_nodes(&node_model, &positions);
+        let network = SpringNetwork::cloth(&self.physics, &bodies, columns, spring);
+        self.physics.add_spring_network(network)
+    }
+
+    /// Add a soft blob of `nodes` objects drawn with `node_model` in a ring around `center`, linked by springs and filled with gas
+    pub fn add_soft_blob(
+        &mut self,
+        center: (f32, f32),
+        radius: f32,
+        nodes: usize,
+        node_model: Primitive,
+        spring: Spring,
+        pressure: f32,
+    ) -> SpringNetworkId {
+        let positions: Vec<(f32, f32)> = (0..nodes)
+            .map(|i| {
+                let angle = std::f32::consts::TAU * i as f32 / nodes as f32;
+                vector::add(center, vector::rotate((radius, 0.0), angle))
+            })
+            .collect();
+
+        let bodies = self.add_nodes(&node_model, &positions);
+        let network = SpringNetwork::blob(&self.physics, &bodies, spring, pressure);
+        self.physics.add_spring_network(network)
+    }
+
+    /// Drop the objects whose bodies the world bounds removed during the last physics update
+    fn remove_despawned_objects(&mut self) {
+        let despawned = self.physics.take_despawned();
+        if !despawned.is_empty() {
+            self.objects
+                .retain(|object| !despawned.contains(&object.physics_body));
+        }
     }
 
     /// Draw all elements in the simulation
+    ///
+    /// Poses are interpolated between the last two physics steps so motion stays smooth when the frame rate and physics rate differ.
     pub fn draw_all(&mut self) {
         for object in &self.objects {
-            self.renderer.draw_primitive(
-                &object.graphics_model,
-                &self.physics.get_object(object.physics_body).unwrap().pose,
-            );
+            // The body may have been removed from the physics engine directly
+            let Some(body) = self.physics.get_object(object.physics_body) else {
+                continue;
+            };
+            let pose = body
+                .previous_pose
+                .interpolate(&body.pose, self.interpolation_alpha);
+
+            self.renderer.draw_primitive(&object.graphics_model, &pose);
+        }
+
+        self.draw_spring_networks();
+        self.draw_joints();
+
+        if self.show_bounds {
+            self.draw_bounds();
+        }
+    }
+
+    /// Keep lines about the same thickness on screen at any zoom
+    fn line_width(&self) -> f32 {
+        let (p1, p2) = self.renderer.get_physics_view_region();
+        0.004 * (p2.0 - p1.0).abs().max((p2.1 - p1.1).abs())
+    }
+
+    /// Draw each spring as a thin line between the bodies it links
+    fn draw_spring_networks(&mut self) {
+        let width = self.line_width() / 2.0;
+        let color = Color::from_rgb(150, 150, 170);
+
+        let position = |id| {
+            let body: &physics::Body = self.physics.get_object(id)?;
+            Some(
+                body.previous_pose
+                    .interpolate(&body.pose, self.interpolation_alpha)
+                    .position,
+            )
+        };
+        for network in self.physics.spring_networks() {
+            for link in &network.links {
+                if let (Some(a), Some(b)) = (position(link.a), position(link.b)) {
+                    self.renderer.draw_line(a, b, width, color);
+                }
+            }
+        }
+    }
+
+    /// Draw each joint as lines from its bodies to their anchors and between the anchors
+    ///
+    /// Static bodies are left out, since they are often large scenery whose position is far from the joint.
+    fn draw_joints(&mut self) {
+        let width = self.line_width();
+        let color = Color::from_rgb(255, 200, 60);
+
+        for joint in self.physics.joints() {
+            let (Some(a), Some(b)) = (
+                self.physics.get_object(joint.a),
+                self.physics.get_object(joint.b),
+            ) else {
+                continue;
+            };
+            let pose_a = a
+                .previous_pose
+                .interpolate(&a.pose, self.interpolation_alpha);
+            let pose_b = b
+                .previous_pose
+                .interpolate(&b.pose, self.interpolation_alpha);
+            let anchor_a = joint.anchor_a(&pose_a);
+            let anchor_b = joint.anchor_b(&pose_b);
+
+            for (body, pose, anchor) in [(a, pose_a, anchor_a), (b, pose_b, anchor_b)] {
+                if !body.dynamics.is_static() {
+                    self.renderer.draw_line(pose.position, anchor, width, color);
+                }
+            }
+            self.renderer.draw_line(anchor_a, anchor_b, width, color);
+        }
+    }
+
+    /// Draw each edge of the world bounds in a color for its behavior
+    fn draw_bounds(&mut self) {
+        let Some(bounds) = self.physics.bounds else {
+            return;
+        };
+
+        let width = self.line_width();
+
+        for edge in bounds.edges() {
+            let color = match edge.behavior {
+                EdgeBehavior::Wall { .. } => Color::from_rgb(230, 230, 230),
+                EdgeBehavior::Wrap => Color::from_rgb(60, 200, 255),
+                EdgeBehavior::Despawn => Color::from_rgb(255, 70, 70),
+            };
+            self.renderer.draw_line(edge.from, edge.to, width, color);
         }
     }
 
@@ -179,3 +754,78 @@ where
         self.renderer.end_frame();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderer::{Renderer, SvgRenderer, ViewFit};
+
+    fn assert_region_close(actual: ((f32, f32), (f32, f32)), expected: ((f32, f32), (f32, f32))) {
+        let corners = [
+            (actual.0 .0, expected.0 .0),
+            (actual.0 .1, expected.0 .1),
+            (actual.1 .0, expected.1 .0),
+            (actual.1 .1, expected.1 .1),
+        ];
+        assert!(
+            corners.iter().all(|(a, e)| (a - e).abs() < 1e-3),
+            "expected {expected:?}, got {actual:?}"
+        );
+    }
+
+    #[test]
+    fn zooming_keeps_the_requested_region_across_view_fits() {
+        let mut simulation = Simulation::new(SvgRenderer::new((100, 100)));
+        let requested = ((0.0, 0.0), (20.0, 10.0));
+        simulation.set_view_region(requested);
+
+        // Cropping the region to fill the surface must not feed back into the region that is zoomed
+        for _ in 0..6 {
+            let fit = simulation.renderer.get_view_fit().next();
+            simulation.renderer.set_view_fit(fit);
+        }
+        assert_eq!(simulation.renderer.get_view_fit(), ViewFit::Fill);
+
+        let second = Duration::from_secs(1);
+        simulation.inputs.view_region_zoom_speed = 1.0;
+        simulation.update(second);
+        assert_region_close(simulation.view_region(), ((5.0, 2.5), (15.0, 7.5)));
+
+        simulation.inputs.view_region_zoom_speed = -1.0;
+        simulation.update(second);
+        assert_region_close(simulation.view_region(), requested);
+        assert_region_close(
+            simulation.renderer.get_physics_view_region(),
+            ((5.0, 0.0), (15.0, 10.0)),
+        );
+
+        simulation.renderer.set_view_fit(ViewFit::Letterbox);
+        assert_region_close(
+            simulation.renderer.get_physics_view_region(),
+            ((0.0, -5.0), (20.0, 15.0)),
+        );
+    }
+
+    #[test]
+    fn cursor_follows_the_view_while_it_scrolls() {
+        let mut simulation = Simulation::new(SvgRenderer::new((100, 100)));
+        simulation.set_view_region(((0.0, 0.0), (10.0, 10.0)));
+        simulation.inputs.cursor_pixel = Some((50.0, 50.0));
+
+        simulation.update(Duration::ZERO);
+        assert!(
+            vector::length(vector::sub(simulation.inputs.cursor_position, (5.0, 5.0))) < 1e-4,
+            "{:?}",
+            simulation.inputs.cursor_position
+        );
+
+        // Scroll right by 2 units without moving the cursor
+        simulation.inputs.view_region_scroll_speed = (2.0, 0.0);
+        simulation.update(Duration::from_secs(1));
+        assert!(
+            vector::length(vector::sub(simulation.inputs.cursor_position, (7.0, 5.0))) < 1e-4,
+            "{:?}",
+            simulation.inputs.cursor_position
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Recording simulation states so they can be rewound and replayed
+
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use super::Object;
+use crate::physics::{PhysicsSettings, PhysicsSnapshot};
+
+/// The complete state of a simulation at one physics step
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub(super) objects: Vec<Object>,
+    pub(super) object_uid_counter: u32,
+    pub(super) physics: PhysicsSnapshot,
+}
+
+/// A bounded ring buffer of the most recent snapshots
+///
+/// Once full, recording a new snapshot drops the oldest one, so memory use stays fixed no matter how long the simulation runs. Each snapshot is a full copy of every object and body, so the capacity should shrink as scenes grow. A capacity of 0 records nothing.
+pub struct History {
+    snapshots: VecDeque<Snapshot>,
+    capacity: usize,
+}
+
+impl History {
+    pub fn new(capacity: usize) -> History {
+        History {
+            snapshots: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Add a snapshot as the newest, dropping the oldest if the buffer is full
+    pub fn record(&mut self, snapshot: Snapshot) {
+        if self.capacity == 0 {
+            return;
+        }
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// The snapshot at an index, counting from the oldest
+    pub fn get(&self, index: usize) -> Option<&Snapshot> {
+        self.snapshots.get(index)
+    }
+
+    /// Drop every snapshot newer than the first `len`
+    pub fn truncate(&mut self, len: usize) {
+        self.snapshots.truncate(len);
+    }
+
+    /// All snapshots, oldest first
+    pub fn iter(&self) -> impl Iterator<Item = &Snapshot> {
+        self.snapshots.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    /// The most snapshots kept at once
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Change how many snapshots are kept, dropping the oldest ones that no longer fit
+    pub fn set_capacity(&mut self, capacity: usize) {
+        let excess = self.snapshots.len().saturating_sub(capacity);
+        self.snapshots.drain(..excess);
+        self.snapshots.shrink_to(capacity);
+        self.capacity = capacity;
+    }
+}
+
+/// A sequence of snapshots saved to disk for replaying later
+#[derive(Serialize, Deserialize)]
+pub struct Recording {
+    /// The fixed physics step the snapshots were taken at
+    pub physics_step: Duration,
+
+    /// The region of the physics simulation shown when the recording was made
+    pub view_region: ((f32, f32), (f32, f32)),
+
+    /// How the physics engine was set up, so simulating on from the recording carries on the same way
+    #[serde(default)]
+    pub physics: PhysicsSettings,
+
+    /// One snapshot per physics step, oldest first
+    pub snapshots: Vec<Snapshot>,
+}
+
+impl Recording {
+    /// Read a recording file
+    pub fn load(path: impl AsRef<Path>) -> Result<Recording, RecordingError> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Write the recording to a file
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), RecordingError> {
+        // Every field is plain data, so serialization cannot fail
+        Ok(fs::write(path, serde_json::to_string(self).unwrap())?)
+    }
+}
+
+/// An error from reading or writing a recording file
+#[derive(Debug)]
+pub enum RecordingError {
+    Io(io::Error),
+    Format(serde_json::Error),
+}
+
+impl fmt::Display for RecordingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RecordingError::Io(error) => write!(f, "could not access file: {error}"),
+            RecordingError::Format(error) => write!(f, "invalid file contents: {error}"),
+        }
+    }
+}
+
+impl Error for RecordingError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            RecordingError::Io(error) => Some(error),
+            RecordingError::Format(error) => Some(error),
+        }
+    }
+}
+
+impl From<io::Error> for RecordingError {
+    fn from(error: io::Error) -> RecordingError {
+        RecordingError::Io(error)
+    }
+}
+
+impl From<serde_json::Error> for RecordingError {
+    fn from(error: serde_json::Error) -> RecordingError {
+        RecordingError::Format(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::physics::force_field::LinearDrag;
+    use crate::physics::integrator::{Integrator, VelocityVerlet};
+    use crate::physics::Pose;
+    use crate::renderer::SvgRenderer;
+    use crate::scene::Scene;
+    use crate::simulation::Simulation;
+
+    /// The exact bits of every body's pose, so comparisons cannot hide rounding
+    fn pose_bits(simulation: &Simulation<SvgRenderer>) -> Vec<[u32; 3]> {
+        let bits = |pose: &Pose| {
+            [
+                pose.position.0.to_bits(),
+                pose.position.1.to_bits(),
+                pose.orientation.to_bits(),
+            ]
+        };
+        simulation
+            .physics
+            .objects()
+            .iter()
+            .map(|body| bits(&body.pose))
+            .collect()
+    }
+
+    #[test]
+    fn history_is_off_until_given_a_capacity() {
+        let mut simulation = Simulation::new(SvgRenderer::new((100, 100)));
+        Scene::from_json(include_str!("../../scenes/demo.json"))
+            .unwrap()
+            .build(&mut simulation);
+
+        simulation.update(Duration::from_millis(50));
+        assert!(simulation.history.is_empty());
+
+        simulation.history.set_capacity(3);
+        simulation.update(Duration::from_millis(50));
+        assert_eq!(simulation.history.len(), 3);
+
+        simulation.history.set_capacity(2);
+        assert_eq!(simulation.history.len(), 2);
+    }
+
+    #[test]
+    fn saved_recording_replays_bit_for_bit() {
+        let steps = 240;
+        let extra_steps = 120;
+
+        // Gravity, bounds and a combine rule from the scene, plus settings only the engine has
+        let scene = Scene::from_json(include_str!("../../scenes/materials.json")).unwrap();
+        let mut original = Simulation::new(SvgRenderer::new((100, 100)));
+        original.history.set_capacity(steps);
+        scene.build(&mut original);
+        original.physics.set_integrator(Box::new(VelocityVerlet));
+        original
+            .physics
+            .add_force_field(Box::new(LinearDrag { coefficient: 0.5 }));
+        original.physics.solver.velocity_iterations = 12;
+
+        let step = original.physics_step;
+        for _ in 0..steps {
+            original.update(step);
+        }
+        let recorded = pose_bits(&original);
+
+        let path = std::env::temp_dir().join(format!("replay_test_{}.json", std::process::id()));
+        original.recording().save(&path).unwrap();
+        let recording = Recording::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(recording.snapshots.len(), steps);
+
+        for _ in 0..extra_steps {
+            original.update(step);
+        }
+
+        // Playing back shows each snapshot in turn and stops on the last
+        let mut replay = Simulation::new(SvgRenderer::new((100, 100)));
+        replay.play_recording(recording);
+        for _ in 0..steps {
+            replay.update(step);
+        }
+        assert!(!replay.playing_history);
+        assert_eq!(pose_bits(&replay), recorded);
+
+        // Simulating on from the end with the recorded settings follows the original exactly
+        assert_eq!(replay.physics.integrator().name(), VelocityVerlet.name());
+        replay.resume();
+        for _ in 0..extra_steps {
+            replay.update(step);
+        }
+        assert_eq!(pose_bits(&replay), pose_bits(&original));
+    }
+}